@@ -0,0 +1,1662 @@
+//! Baskets of scanned products and the deals that apply to them.
+
+use std::{collections::BTreeMap, error::Error, fmt::Display, str::FromStr, sync::Arc};
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+use crate::{
+    catalog::{Catalog, Product, Unit},
+    clock::{Clock, SystemClock},
+    deal::{Deal, DealError, Percentage, Rounding, Stacking},
+    event::{Action, Event},
+    exchange::{ExchangeRate, ExchangeRates},
+    group::{self, GroupDeal},
+    money::{CurrencyCode, Money, MoneyError},
+    optimizer,
+    receipt::{Adjustment, AdjustmentDeal, Receipt, ReceiptLine},
+    rule::Rule,
+    tax::{self, PricingMode, TaxClass, TaxJurisdiction, TaxPolicy},
+    threshold::{ThresholdDeal, ThresholdReward},
+};
+
+/// A customer's basket. It owns its contents and shares the catalog, so it can be kept around
+/// and sent between threads independently of whatever created it.
+#[derive(Debug, Clone)]
+pub struct Basket {
+    catalog: Arc<Catalog>,
+    contents: Contents,
+    line_order: LineOrder,
+    rounding: Rounding,
+    taxes: Option<Taxes>,
+    clock: Arc<dyn Clock>,
+    events: Vec<Event>,
+    /// The contents before each change that can still be undone, most recent last.
+    history: Vec<Contents>,
+    /// The contents before each undo that can still be redone, most recent last.
+    undone: Vec<Contents>,
+}
+
+/// What a basket holds, as opposed to how it is configured.
+#[derive(Debug, Clone, Default)]
+struct Contents {
+    /// The SKU and quantity of each line, one per product, in the order the products were first
+    /// added.
+    lines: Vec<(String, u32)>,
+    deals: Vec<Arc<Deal>>,
+    group_deals: Vec<Arc<GroupDeal>>,
+    threshold_deals: Vec<Arc<ThresholdDeal>>,
+    rules: Vec<Arc<Rule>>,
+}
+
+/// Where the tax rates of a basket come from.
+#[derive(Debug, Clone)]
+enum Taxes {
+    Fixed(TaxPolicy),
+    /// The rates of the jurisdiction in force on the day the receipt is priced.
+    Jurisdiction(Arc<TaxJurisdiction>),
+}
+
+/// A line priced by [`Basket::price_line`].
+struct PricedLine<'d> {
+    net: Money,
+    /// The deals that were used.
+    deals: Vec<&'d Deal>,
+    /// How many units were not priced under an exclusive deal, and what they cost. Only these
+    /// units are left for rules to discount.
+    open: (u32, Money),
+}
+
+/// The order in which basket lines are listed on a receipt.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum LineOrder {
+    /// The order in which products were first added to the basket.
+    #[default]
+    Scanned,
+    Sku,
+    Name,
+}
+
+/// A basket total converted into the currency the customer pays in.
+#[derive(Debug, PartialEq)]
+pub struct ConvertedTotal {
+    pub total: Money,
+    pub converted: Money,
+    pub rate: ExchangeRate,
+}
+
+/// Why an operation on a [`Basket`] failed.
+#[derive(Debug, PartialEq)]
+pub enum BasketError {
+    UnknownSku(String),
+    NotInBasket(String),
+    InvalidQuantity {
+        sku: String,
+        quantity: u32,
+    },
+    DealReferencesMissingProduct(String),
+    /// The configuration of a deal makes no sense.
+    InvalidDeal(DealError),
+    CurrencyMismatch {
+        expected: CurrencyCode,
+        found: CurrencyCode,
+    },
+    MissingExchangeRate {
+        from: CurrencyCode,
+        to: CurrencyCode,
+    },
+    /// The SKU was scanned, or a deal was added for it, in a way that does not fit how the
+    /// product is sold.
+    UnitMismatch {
+        sku: String,
+        unit: Unit,
+    },
+    NothingToUndo,
+    NothingToRedo,
+    MissingTaxRate {
+        jurisdiction: String,
+        class: TaxClass,
+        date: NaiveDate,
+    },
+    Overflow,
+}
+
+impl Display for BasketError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BasketError::UnknownSku(sku) => write!(f, "unknown sku '{sku}'"),
+            BasketError::NotInBasket(sku) => write!(f, "sku '{sku}' is not in the basket"),
+            BasketError::InvalidQuantity { sku, quantity } => {
+                write!(f, "invalid quantity {quantity} for sku '{sku}'")
+            }
+            BasketError::DealReferencesMissingProduct(sku) => {
+                write!(f, "deal references sku '{sku}' which is not in the catalog")
+            }
+            BasketError::InvalidDeal(error) => write!(f, "invalid deal: {error}"),
+            BasketError::CurrencyMismatch { expected, found } => {
+                write!(f, "cannot combine {found} with {expected}")
+            }
+            BasketError::MissingExchangeRate { from, to } => {
+                write!(f, "no exchange rate from {from} to {to}")
+            }
+            BasketError::UnitMismatch { sku, unit } => write!(f, "sku '{sku}' is sold per {unit}"),
+            BasketError::NothingToUndo => f.write_str("nothing to undo"),
+            BasketError::NothingToRedo => f.write_str("nothing to redo"),
+            BasketError::MissingTaxRate {
+                jurisdiction,
+                class,
+                date,
+            } => write!(f, "no {class} tax rate in {jurisdiction} on {date}"),
+            BasketError::Overflow => f.write_str("arithmetic overflow while computing total"),
+        }
+    }
+}
+
+impl Error for BasketError {}
+
+impl FromStr for LineOrder {
+    type Err = String;
+
+    fn from_str(order: &str) -> Result<Self, Self::Err> {
+        match order {
+            "scanned" => Ok(LineOrder::Scanned),
+            "sku" => Ok(LineOrder::Sku),
+            "name" => Ok(LineOrder::Name),
+            _ => Err(format!("unknown line order '{order}'")),
+        }
+    }
+}
+
+impl From<MoneyError> for BasketError {
+    fn from(error: MoneyError) -> Self {
+        match error {
+            MoneyError::CurrencyMismatch { expected, found } => {
+                BasketError::CurrencyMismatch { expected, found }
+            }
+            MoneyError::Overflow => BasketError::Overflow,
+        }
+    }
+}
+
+impl Basket {
+    /// Creates an empty basket that prices products from `catalog`.
+    pub fn new(catalog: Arc<Catalog>) -> Self {
+        Basket {
+            catalog,
+            contents: Contents::default(),
+            line_order: LineOrder::default(),
+            rounding: Rounding::default(),
+            taxes: None,
+            clock: Arc::new(SystemClock),
+            events: Vec::new(),
+            history: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    /// Rebuilds a basket by performing the actions in `events` in order, keeping their
+    /// sequence numbers and timestamps.
+    pub fn replay(catalog: Arc<Catalog>, events: &[Event]) -> Result<Self, BasketError> {
+        let mut basket = Basket::new(catalog);
+
+        for event in events {
+            match &event.action {
+                Action::Scan { sku, quantity } => basket.scan_quantity(sku, *quantity),
+                Action::ScanWeighted { sku, grams } => basket.scan_weighted(sku, *grams),
+                Action::Remove { sku } => basket.remove(sku),
+                Action::SetQuantity { sku, quantity } => basket.set_quantity(sku, *quantity),
+                Action::VoidLine { sku } => basket.void_line(sku),
+                Action::AddDeal { deal } => basket.add_deal(deal.clone()),
+                Action::AddGroupDeal { deal } => basket.add_group_deal(deal.clone()),
+                Action::AddThresholdDeal { deal } => basket.add_threshold_deal(deal.clone()),
+                Action::AddRule { rule } => basket.add_rule(rule.clone()),
+                Action::Undo => basket.undo(),
+                Action::Redo => basket.redo(),
+            }?;
+
+            basket.events.pop();
+            basket.events.push(event.clone());
+        }
+
+        Ok(basket)
+    }
+
+    /// Every successful change to the basket, oldest first.
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// Reverts the most recent change that has not been undone yet.
+    pub fn undo(&mut self) -> Result<(), BasketError> {
+        let previous = self.history.pop().ok_or(BasketError::NothingToUndo)?;
+
+        self.undone
+            .push(std::mem::replace(&mut self.contents, previous));
+        self.log(Action::Undo);
+
+        Ok(())
+    }
+
+    /// Reapplies the most recently undone change, unless the basket was changed since.
+    pub fn redo(&mut self) -> Result<(), BasketError> {
+        let next = self.undone.pop().ok_or(BasketError::NothingToRedo)?;
+
+        self.history
+            .push(std::mem::replace(&mut self.contents, next));
+        self.log(Action::Redo);
+
+        Ok(())
+    }
+
+    /// Sets how percentage discounts are rounded to a whole minor unit.
+    pub fn set_rounding(&mut self, rounding: Rounding) {
+        self.rounding = rounding;
+    }
+
+    /// Sets how the basket is taxed. Without a policy, receipts carry no tax summary and
+    /// totals are the catalog prices after deals.
+    pub fn set_tax_policy(&mut self, policy: TaxPolicy) {
+        self.taxes = Some(Taxes::Fixed(policy));
+    }
+
+    /// Taxes the basket at the rates `jurisdiction` charges on the day it is priced, so a
+    /// rate change takes effect without reconfiguring the basket.
+    pub fn set_jurisdiction(&mut self, jurisdiction: Arc<TaxJurisdiction>) {
+        self.taxes = Some(Taxes::Jurisdiction(jurisdiction));
+    }
+
+    /// Sets the clock that decides when the basket is priced, and so which deals are valid and
+    /// which tax rates are in force, and that timestamps its events. The system clock is used
+    /// by default.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// Adds one unit of `sku`.
+    pub fn scan(&mut self, sku: &str) -> Result<(), BasketError> {
+        self.scan_quantity(sku, 1)
+    }
+
+    /// Adds `quantity` units of `sku`, which must be sold per piece.
+    pub fn scan_quantity(&mut self, sku: &str, quantity: u32) -> Result<(), BasketError> {
+        let action = Action::Scan {
+            sku: sku.to_string(),
+            quantity,
+        };
+
+        self.record(action, |basket| basket.add(sku, quantity, false))
+    }
+
+    /// Adds `grams` of a product sold per kilogram, or millilitres of one sold per litre.
+    pub fn scan_weighted(&mut self, sku: &str, grams: u32) -> Result<(), BasketError> {
+        let action = Action::ScanWeighted {
+            sku: sku.to_string(),
+            grams,
+        };
+
+        self.record(action, |basket| basket.add(sku, grams, true))
+    }
+
+    /// Takes one unit of `sku` out of the basket, removing the line with the last unit.
+    pub fn remove(&mut self, sku: &str) -> Result<(), BasketError> {
+        let product = self.product(sku)?;
+
+        if product.unit.is_measured() {
+            return Err(BasketError::UnitMismatch {
+                sku: sku.to_string(),
+                unit: product.unit,
+            });
+        }
+
+        let index = self
+            .position(sku)
+            .ok_or_else(|| BasketError::NotInBasket(sku.to_string()))?;
+        let quantity = self.contents.lines[index].1 - 1;
+
+        self.record(
+            Action::Remove {
+                sku: sku.to_string(),
+            },
+            |basket| basket.set_line(sku, quantity),
+        )
+    }
+
+    /// Replaces the quantity of `sku`, in grams or millilitres for products sold by weight or
+    /// volume. Setting it to zero removes the line.
+    pub fn set_quantity(&mut self, sku: &str, quantity: u32) -> Result<(), BasketError> {
+        self.product(sku)?;
+        let action = Action::SetQuantity {
+            sku: sku.to_string(),
+            quantity,
+        };
+
+        self.record(action, |basket| basket.set_line(sku, quantity))
+    }
+
+    /// Removes every unit of `sku` from the basket.
+    pub fn void_line(&mut self, sku: &str) -> Result<(), BasketError> {
+        self.product(sku)?;
+
+        if self.position(sku).is_none() {
+            return Err(BasketError::NotInBasket(sku.to_string()));
+        }
+
+        self.record(
+            Action::VoidLine {
+                sku: sku.to_string(),
+            },
+            |basket| basket.set_line(sku, 0),
+        )
+    }
+
+    /// Sets the order in which lines are listed on the receipt.
+    pub fn set_line_order(&mut self, order: LineOrder) {
+        self.line_order = order;
+    }
+
+    /// Makes `deal` available to the lines of its product, if its configuration makes sense
+    /// even when it was not built with [`Deal::builder`]. Deals that count units cannot
+    /// target a product sold by weight or volume, and a price or amount off must be in the
+    /// catalog's currency.
+    pub fn add_deal(&mut self, deal: Deal) -> Result<(), BasketError> {
+        deal.validate().map_err(BasketError::InvalidDeal)?;
+        let product = self
+            .catalog
+            .get(&deal.product)
+            .ok_or_else(|| BasketError::DealReferencesMissingProduct(deal.product.clone()))?;
+
+        if product.unit.is_measured() && deal.kind.counts_units() {
+            return Err(BasketError::UnitMismatch {
+                sku: product.sku.clone(),
+                unit: product.unit,
+            });
+        }
+        if let Some(amount) = deal.kind.amount() {
+            self.expect_currency(amount)?;
+        }
+
+        self.record(Action::AddDeal { deal: deal.clone() }, |basket| {
+            basket.contents.deals.push(Arc::new(deal));
+            Ok(())
+        })
+    }
+
+    /// Makes `deal` available to the basket. Every SKU it refers to must be in the catalog, and
+    /// its price in the catalog's currency.
+    pub fn add_group_deal(&mut self, deal: GroupDeal) -> Result<(), BasketError> {
+        deal.validate().map_err(BasketError::InvalidDeal)?;
+        if let Some(sku) = deal.skus().find(|sku| self.catalog.get(sku).is_none()) {
+            return Err(BasketError::DealReferencesMissingProduct(sku.clone()));
+        }
+        if let Some(amount) = deal.amount() {
+            self.expect_currency(amount)?;
+        }
+
+        self.record(Action::AddGroupDeal { deal: deal.clone() }, |basket| {
+            basket.contents.group_deals.push(Arc::new(deal));
+            Ok(())
+        })
+    }
+
+    /// Makes `deal` available to the basket. Its threshold, and the amount it takes off if any,
+    /// must be in the catalog's currency.
+    pub fn add_threshold_deal(&mut self, deal: ThresholdDeal) -> Result<(), BasketError> {
+        self.expect_currency(deal.threshold)?;
+        if let ThresholdReward::AmountOff(amount) = deal.reward {
+            self.expect_currency(amount)?;
+        }
+
+        self.record(Action::AddThresholdDeal { deal: deal.clone() }, |basket| {
+            basket.contents.threshold_deals.push(Arc::new(deal));
+            Ok(())
+        })
+    }
+
+    /// Adds a promotion rule. Every SKU it names must be in the catalog, its amounts must be in
+    /// the catalog's currency, and it must be valid at some point.
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), BasketError> {
+        rule.action.validate().map_err(BasketError::InvalidDeal)?;
+        if rule.validity.is_empty() {
+            return Err(BasketError::InvalidDeal(DealError::EmptyValidity));
+        }
+        if let Some(sku) = rule.skus().find(|sku| self.catalog.get(sku).is_none()) {
+            return Err(BasketError::DealReferencesMissingProduct(sku.clone()));
+        }
+        for amount in rule.amounts() {
+            self.expect_currency(amount)?;
+        }
+
+        self.record(Action::AddRule { rule: rule.clone() }, |basket| {
+            basket.contents.rules.push(Arc::new(rule));
+            Ok(())
+        })
+    }
+
+    /// The amount the customer pays, after every deal that is valid at the time on the clock.
+    pub fn total(&self) -> Result<Money, BasketError> {
+        Ok(self.receipt()?.total)
+    }
+
+    /// Prices the basket and itemizes how the total was reached.
+    ///
+    /// Group deals are applied first, in priority order, and the units they claim are not
+    /// eligible for product deals. The remaining units of each product are then split between
+    /// its deals in whichever way is cheapest. Rules that are valid follow in the order they were
+    /// added. Like stackable deals, they only apply to units that no group deal or exclusive deal
+    /// has claimed, and each takes its action on what those units still cost. Threshold deals
+    /// are evaluated last, on the total after every other deal, and only the one that saves the
+    /// customer the most is applied.
+    ///
+    /// Lines are listed in the order set with [`Basket::set_line_order`].
+    pub fn receipt(&self) -> Result<Receipt, BasketError> {
+        let currency = self.catalog.currency();
+        let now = self.clock.now();
+        let mut lines = self
+            .contents
+            .lines
+            .iter()
+            .map(|(sku, quantity)| Ok((self.product(sku)?, *quantity)))
+            .collect::<Result<Vec<group::Line>, BasketError>>()?;
+        match self.line_order {
+            LineOrder::Scanned => {}
+            LineOrder::Sku => lines.sort_by(|(a, _), (b, _)| a.sku.cmp(&b.sku)),
+            LineOrder::Name => {
+                lines.sort_by(|(a, _), (b, _)| a.name.cmp(&b.name).then(a.sku.cmp(&b.sku)))
+            }
+        }
+
+        let mut group_deals: Vec<&GroupDeal> =
+            self.contents.group_deals.iter().map(Arc::as_ref).collect();
+        group_deals.sort_by_key(|deal| deal.priority);
+
+        let mut unclaimed = lines.clone();
+        let mut adjustments = Vec::new();
+        // How much of the adjustments each line bears, which lowers its taxable amount.
+        let mut shares = vec![Money::zero(currency); lines.len()];
+        for deal in group_deals {
+            let before = unclaimed.clone();
+            let claim = deal.apply(&mut unclaimed, currency)?;
+            let amount = claim.regular.saturating_sub(claim.charged)?;
+
+            if amount.amount > 0 {
+                let claimed: Vec<u64> = before
+                    .iter()
+                    .zip(&unclaimed)
+                    .map(|((product, before), (_, after))| {
+                        product
+                            .price
+                            .amount
+                            .saturating_mul(u64::from(before - after))
+                    })
+                    .collect();
+
+                share(&mut shares, amount, &claimed)?;
+                adjustments.push(Adjustment {
+                    deal: AdjustmentDeal::Group(deal.clone()),
+                    amount,
+                });
+            }
+        }
+
+        let mut receipt_lines = Vec::new();
+        // The units of each line that neither a group deal nor an exclusive deal has claimed, and
+        // what they still cost.
+        let mut open = Vec::new();
+        for ((product, quantity), (_, unclaimed)) in lines.into_iter().zip(unclaimed) {
+            let gross = product.unit.price(product.price, quantity)?;
+            let priced = self.price_line(product, unclaimed, now)?;
+            let discount = product
+                .unit
+                .price(product.price, unclaimed)?
+                .saturating_sub(priced.net)?;
+            let deals = if discount.amount > 0 {
+                priced.deals.iter().map(|deal| deal.kind.clone()).collect()
+            } else {
+                Vec::new()
+            };
+            open.push(priced.open);
+
+            receipt_lines.push(ReceiptLine {
+                sku: product.sku.clone(),
+                name: product.name.clone(),
+                quantity,
+                unit: product.unit,
+                tax_class: product.tax_class,
+                unit_price: product.price,
+                gross,
+                deals,
+                discount,
+                net: gross.saturating_sub(discount)?,
+            });
+        }
+
+        let subtotal = Money::checked_sum(currency, receipt_lines.iter().map(|line| line.gross))?;
+
+        for rule in self
+            .contents
+            .rules
+            .iter()
+            .map(Arc::as_ref)
+            .filter(|rule| rule.validity.contains(now))
+        {
+            let mut amount = Money::zero(currency);
+            for ((line, share), (quantity, remaining)) in receipt_lines
+                .iter()
+                .zip(shares.iter_mut())
+                .zip(open.iter_mut())
+            {
+                let product = self.product(&line.sku)?;
+                if *quantity == 0 || !rule.condition.matches(product, *quantity, subtotal) {
+                    continue;
+                }
+
+                let discounted = if line.unit.is_measured() {
+                    rule.action
+                        .apply_measured(*remaining, *quantity, line.unit, self.rounding)?
+                } else {
+                    rule.action.apply(*remaining, *quantity, self.rounding)?
+                };
+                let saving = remaining.saturating_sub(discounted)?;
+
+                *remaining = discounted;
+                *share = share.checked_add(saving)?;
+                amount = amount.checked_add(saving)?;
+            }
+
+            if amount.amount > 0 {
+                adjustments.push(Adjustment {
+                    deal: AdjustmentDeal::Rule(rule.action.clone()),
+                    amount,
+                });
+            }
+        }
+        let discounted = subtotal
+            .saturating_sub(Money::checked_sum(
+                currency,
+                receipt_lines.iter().map(|line| line.discount),
+            )?)?
+            .saturating_sub(Money::checked_sum(
+                currency,
+                adjustments.iter().map(|adjustment| adjustment.amount),
+            )?)?;
+
+        let mut best: Option<(&ThresholdDeal, Money)> = None;
+        for deal in self.contents.threshold_deals.iter().map(Arc::as_ref) {
+            if let Some(total) = deal.apply(subtotal, discounted)? {
+                if best.is_none_or(|(_, best)| total.amount < best.amount) {
+                    best = Some((deal, total));
+                }
+            }
+        }
+        if let Some((deal, total)) = best {
+            let amount = discounted.saturating_sub(total)?;
+            let remaining = receipt_lines
+                .iter()
+                .zip(&shares)
+                .map(|(line, share)| Ok(line.net.saturating_sub(*share)?.amount))
+                .collect::<Result<Vec<u64>, BasketError>>()?;
+
+            share(&mut shares, amount, &remaining)?;
+            adjustments.push(Adjustment {
+                deal: AdjustmentDeal::Threshold(deal.clone()),
+                amount,
+            });
+        }
+
+        let savings = Money::checked_sum(
+            currency,
+            receipt_lines
+                .iter()
+                .map(|line| line.discount)
+                .chain(adjustments.iter().map(|adjustment| adjustment.amount)),
+        )?;
+
+        let mut taxes = Vec::new();
+        let mut total = subtotal.saturating_sub(savings)?;
+        let (policy, tax_label) = match &self.taxes {
+            None => (None, tax::default_label()),
+            Some(Taxes::Fixed(policy)) => (Some(*policy), tax::default_label()),
+            Some(Taxes::Jurisdiction(jurisdiction)) => (
+                Some(jurisdiction.policy_on(now.date())?),
+                jurisdiction.label.clone(),
+            ),
+        };
+        if let Some(policy) = policy {
+            let mut classes: BTreeMap<TaxClass, Money> = BTreeMap::new();
+            for (line, share) in receipt_lines.iter().zip(&shares) {
+                let class = classes
+                    .entry(line.tax_class)
+                    .or_insert(Money::zero(currency));
+                *class = class.checked_add(line.net.saturating_sub(*share)?)?;
+            }
+
+            // Classes that share a rate are taxed together, in the order of the classes.
+            let mut charged: Vec<(Option<Percentage>, Money)> = Vec::new();
+            for (class, amount) in classes {
+                let rate = policy.rates.rate(class);
+                match charged.iter_mut().find(|(other, _)| *other == rate) {
+                    Some((_, total)) => *total = total.checked_add(amount)?,
+                    None => charged.push((rate, amount)),
+                }
+            }
+
+            for (rate, amount) in charged {
+                let summary = policy.summarize(rate, amount)?;
+                if policy.mode == PricingMode::Exclusive {
+                    total = total.checked_add(summary.tax)?;
+                }
+                taxes.push(summary);
+            }
+        }
+
+        Ok(Receipt {
+            lines: receipt_lines,
+            adjustments,
+            subtotal,
+            savings,
+            taxes,
+            pricing: policy.map(|policy| policy.mode).unwrap_or_default(),
+            tax_label,
+            total,
+        })
+    }
+
+    /// Computes the total and converts it into `currency`.
+    ///
+    /// The conversion is applied once to the total after deals, rounding half up to the
+    /// nearest minor unit of `currency`, so that per-line rounding cannot accumulate.
+    pub fn total_in(
+        &self,
+        currency: CurrencyCode,
+        rates: &ExchangeRates,
+    ) -> Result<ConvertedTotal, BasketError> {
+        let total = self.total()?;
+        let rate = rates
+            .get(total.currency, currency)
+            .ok_or(BasketError::MissingExchangeRate {
+                from: total.currency,
+                to: currency,
+            })?;
+
+        Ok(ConvertedTotal {
+            total,
+            converted: rate.convert(total)?,
+            rate,
+        })
+    }
+
+    /// Adds `quantity` of the product `sku`, which must be sold by weight or volume if and only
+    /// if `measured` is set.
+    fn add(&mut self, sku: &str, quantity: u32, measured: bool) -> Result<(), BasketError> {
+        let product = self.product(sku)?;
+
+        if product.unit.is_measured() != measured {
+            return Err(BasketError::UnitMismatch {
+                sku: sku.to_string(),
+                unit: product.unit,
+            });
+        }
+
+        if quantity == 0 {
+            return Err(BasketError::InvalidQuantity {
+                sku: sku.to_string(),
+                quantity,
+            });
+        }
+
+        match self.position(sku) {
+            Some(index) => {
+                let (_, entry) = &mut self.contents.lines[index];
+                *entry = entry.checked_add(quantity).ok_or(BasketError::Overflow)?;
+            }
+            None => self.contents.lines.push((sku.to_string(), quantity)),
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the quantity of `sku`, removing the line if it is zero.
+    fn set_line(&mut self, sku: &str, quantity: u32) -> Result<(), BasketError> {
+        let index = self.position(sku);
+        let lines = &mut self.contents.lines;
+
+        match (index, quantity) {
+            (Some(index), 0) => {
+                lines.remove(index);
+            }
+            (Some(index), quantity) => lines[index].1 = quantity,
+            (None, 0) => {}
+            (None, quantity) => lines.push((sku.to_string(), quantity)),
+        }
+
+        Ok(())
+    }
+
+    /// Performs `change` and logs `action` if it succeeds, so that it can be undone. A failed
+    /// change leaves the basket as it was.
+    fn record(
+        &mut self,
+        action: Action,
+        change: impl FnOnce(&mut Self) -> Result<(), BasketError>,
+    ) -> Result<(), BasketError> {
+        let before = self.contents.clone();
+
+        if let Err(error) = change(self) {
+            self.contents = before;
+            return Err(error);
+        }
+
+        self.history.push(before);
+        self.undone.clear();
+        self.log(action);
+
+        Ok(())
+    }
+
+    fn log(&mut self, action: Action) {
+        let seq = self.events.last().map_or(1, |event| event.seq + 1);
+
+        self.events.push(Event {
+            seq,
+            timestamp: self.clock.timestamp(),
+            action,
+        });
+    }
+
+    fn position(&self, sku: &str) -> Option<usize> {
+        self.contents.lines.iter().position(|(line, _)| line == sku)
+    }
+
+    /// Checks that `amount` is in the catalog's currency, so it can be combined with prices.
+    fn expect_currency(&self, amount: Money) -> Result<(), BasketError> {
+        if amount.currency != self.catalog.currency() {
+            return Err(BasketError::CurrencyMismatch {
+                expected: self.catalog.currency(),
+                found: amount.currency,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn product(&self, sku: &str) -> Result<&Product, BasketError> {
+        self.catalog
+            .get(sku)
+            .ok_or_else(|| BasketError::UnknownSku(sku.to_string()))
+    }
+
+    /// Prices `quantity` units of a product by letting the optimizer split them between the
+    /// deals for the product that are valid at `now`, so the customer always gets the cheapest
+    /// combination.
+    fn price_line(
+        &self,
+        product: &Product,
+        quantity: u32,
+        now: NaiveDateTime,
+    ) -> Result<PricedLine<'_>, BasketError> {
+        let deals: Vec<&Deal> = self
+            .contents
+            .deals
+            .iter()
+            .map(Arc::as_ref)
+            .filter(|deal| deal.product == product.sku && deal.validity.contains(now))
+            .collect();
+
+        let mut net = Money::zero(product.price.currency);
+        let mut used: Vec<&Deal> = Vec::new();
+        let mut open = (0, Money::zero(product.price.currency));
+
+        if quantity == 0 {
+            return Ok(PricedLine {
+                net,
+                deals: used,
+                open,
+            });
+        }
+
+        for allocation in optimizer::optimize(
+            product.price,
+            product.unit,
+            quantity,
+            &deals,
+            self.rounding,
+            optimizer::DEFAULT_MAX_STEPS,
+        )? {
+            net = net.checked_add(allocation.amount)?;
+            if allocation
+                .deals
+                .iter()
+                .all(|deal| deal.stacking == Stacking::Stackable)
+            {
+                open.0 += allocation.quantity;
+                open.1 = open.1.checked_add(allocation.amount)?;
+            }
+
+            for deal in allocation.deals {
+                if !used.iter().any(|other| std::ptr::eq(*other, deal)) {
+                    used.push(deal);
+                }
+            }
+        }
+
+        Ok(PricedLine {
+            net,
+            deals: used,
+            open,
+        })
+    }
+}
+
+/// Adds `amount` to `shares`, split in proportion to `weights`.
+fn share(shares: &mut [Money], amount: Money, weights: &[u64]) -> Result<(), BasketError> {
+    for (share, part) in shares.iter_mut().zip(amount.allocate(weights)) {
+        *share = share.checked_add(part)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::{NaiveDate, NaiveTime, Weekday};
+    use proptest::prelude::*;
+
+    use crate::{
+        basket::{Basket, BasketError},
+        catalog::{Catalog, ProductGroup, Unit},
+        clock::FixedClock,
+        deal::{Deal, DealError, DealKind, Percentage, Rounding, RoundingScope, Stacking},
+        exchange::ExchangeRates,
+        group::{GroupDeal, GroupDealKind},
+        money::{CurrencyCode, Money, RoundingMode},
+        rule::Rule,
+        tax::{PricingMode, TaxJurisdiction, TaxPolicy, TaxRates},
+        test_support::{buy1get1free, catalog},
+        threshold::{ThresholdBase, ThresholdDeal, ThresholdReward},
+        validity::{Recurrence, Validity},
+    };
+
+    fn ten_percent_off() -> Deal {
+        Deal::builder("A0001")
+            .kind(DealKind::PercentageDiscount(
+                Percentage::from_percent(10).unwrap(),
+            ))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_total_without_products() {
+        let basket = Basket::new(catalog());
+
+        assert_eq!(Ok(Money::new(0, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_basket_outlives_its_deals_and_moves_between_threads() {
+        fn assert_shareable<T: Send + Sync + 'static>() {}
+        assert_shareable::<Basket>();
+
+        let mut basket = Basket::new(catalog());
+        {
+            let deal = Deal::builder("A0002")
+                .kind(DealKind::Buy1Get1Free)
+                .build()
+                .unwrap();
+            basket.add_deal(deal).unwrap();
+        }
+
+        let basket = std::thread::spawn(move || {
+            basket.scan_quantity("A0002", 2).unwrap();
+            basket
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(Ok(Money::new(399, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_total_with_products() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+
+        assert_eq!(Ok(Money::new(1698, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_deal1() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+
+        basket.add_deal(buy1get1free()).unwrap();
+
+        assert_eq!(Ok(Money::new(1698, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_deal2() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+
+        basket.add_deal(ten_percent_off()).unwrap();
+
+        assert_eq!(Ok(Money::new(1967, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_deal2_with_rounding() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+
+        basket.add_deal(ten_percent_off()).unwrap();
+        basket.set_rounding(Rounding {
+            mode: RoundingMode::Ceil,
+            scope: RoundingScope::PerUnit,
+        });
+
+        // 12.99 at 10% off is 11.691, rounded up to 11.70.
+        assert_eq!(Ok(Money::new(1968, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_scan_unknown_sku() {
+        let mut basket = Basket::new(catalog());
+
+        assert_eq!(
+            Err(BasketError::UnknownSku("A0009".to_string())),
+            basket.scan("A0009")
+        );
+        assert_eq!(Ok(Money::new(0, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_scan_zero_quantity() {
+        let mut basket = Basket::new(catalog());
+
+        assert_eq!(
+            Err(BasketError::InvalidQuantity {
+                sku: "A0001".to_string(),
+                quantity: 0
+            }),
+            basket.scan_quantity("A0001", 0)
+        );
+    }
+
+    #[test]
+    fn test_deal_for_missing_product() {
+        let mut basket = Basket::new(catalog());
+        let deal = Deal {
+            product: "A0009".to_string(),
+            kind: DealKind::Buy1Get1Free,
+            stacking: Stacking::Exclusive,
+            priority: 0,
+            validity: Validity::default(),
+        };
+
+        assert_eq!(
+            Err(BasketError::DealReferencesMissingProduct(
+                "A0009".to_string()
+            )),
+            basket.add_deal(deal)
+        );
+    }
+
+    #[test]
+    fn test_deal_in_other_currency() {
+        let mut basket = Basket::new(catalog());
+        let deal = Deal::builder("A0001")
+            .kind(DealKind::FixedAmountOff {
+                amount: Money::new(100, CurrencyCode::Usd),
+            })
+            .build()
+            .unwrap();
+
+        basket.scan("A0001").unwrap();
+
+        assert_eq!(
+            Err(BasketError::CurrencyMismatch {
+                expected: CurrencyCode::Eur,
+                found: CurrencyCode::Usd,
+            }),
+            basket.add_deal(deal)
+        );
+        assert_eq!(1, basket.events().len());
+        assert_eq!(Ok(Money::new(1299, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_invalid_deal() {
+        let mut basket = Basket::new(catalog());
+        let deal = Deal {
+            product: "A0002".to_string(),
+            kind: DealKind::BuyNGetMFree { buy: 0, free: 0 },
+            stacking: Stacking::Exclusive,
+            priority: 0,
+            validity: Validity::default(),
+        };
+
+        assert_eq!(
+            Err(BasketError::InvalidDeal(DealError::ZeroQuantity("buy"))),
+            basket.add_deal(deal)
+        );
+        assert!(basket.events().is_empty());
+    }
+
+    fn produce() -> Arc<Catalog> {
+        Arc::new(
+            Catalog::from_csv(
+                "sku,name,price,currency,category,unit\n\
+                 A0001,Water,1299,EUR,,\n\
+                 B0001,Bananas,199,EUR,,kg\n\
+                 M0001,Milk,129,EUR,,l\n",
+            )
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_scan_weighted() {
+        let mut basket = Basket::new(produce());
+
+        // 0.25 kg at 1.99 per kg is 0.4975, rounded half up.
+        basket.scan_weighted("B0001", 250).unwrap();
+        assert_eq!(Ok(Money::new(50, CurrencyCode::Eur)), basket.total());
+
+        // Weights are added up and the line is rounded once: 0.75 kg is 1.4925.
+        basket.scan_weighted("B0001", 500).unwrap();
+        basket.scan_weighted("M0001", 1500).unwrap();
+        assert_eq!(Ok(Money::new(149 + 194, CurrencyCode::Eur)), basket.total());
+    }
+
+    #[test]
+    fn test_scan_rejects_wrong_unit() {
+        let mut basket = Basket::new(produce());
+
+        assert_eq!(
+            Err(BasketError::UnitMismatch {
+                sku: "B0001".to_string(),
+                unit: Unit::Kilogram,
+            }),
+            basket.scan("B0001")
+        );
+        assert_eq!(
+            Err(BasketError::UnitMismatch {
+                sku: "A0001".to_string(),
+                unit: Unit::Piece,
+            }),
+            basket.scan_weighted("A0001", 250)
+        );
+        assert_eq!(
+            Err(BasketError::InvalidQuantity {
+                sku: "B0001".to_string(),
+                quantity: 0,
+            }),
+            basket.scan_weighted("B0001", 0)
+        );
+    }
+
+    #[test]
+    fn test_deals_on_weighted_lines() {
+        let catalog = produce();
+        let twenty_percent = Deal::builder("B0001")
+            .kind(DealKind::PercentageDiscount(
+                Percentage::from_percent(20).unwrap(),
+            ))
+            .build()
+            .unwrap();
+        let fifty_off = Deal::builder("B0001")
+            .kind(DealKind::FixedAmountOff {
+                amount: Money::new(50, CurrencyCode::Eur),
+            })
+            .build()
+            .unwrap();
+        let bogof = Deal::builder("B0001")
+            .kind(DealKind::Buy1Get1Free)
+            .build()
+            .unwrap();
+
+        let total = |deal: &Deal| {
+            let mut basket = Basket::new(Arc::clone(&catalog));
+
+            basket.scan_weighted("B0001", 1250).unwrap();
+            basket.add_deal(deal.clone()).unwrap();
+
+            basket.total().unwrap().amount
+        };
+
+        // 1.25 kg at 1.99 per kg is 2.49.
+        assert_eq!(199, total(&twenty_percent));
+        // 0.50 off per kg is 0.625 off, rounded half up.
+        assert_eq!(186, total(&fifty_off));
+        assert_eq!(
+            Err(BasketError::UnitMismatch {
+                sku: "B0001".to_string(),
+                unit: Unit::Kilogram,
+            }),
+            Basket::new(Arc::clone(&catalog)).add_deal(bogof)
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan_quantity("A0002", 3).unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        assert_eq!(Ok(Money::new(798, CurrencyCode::Eur)), basket.total());
+
+        basket.remove("A0002").unwrap();
+        assert_eq!(Ok(Money::new(399, CurrencyCode::Eur)), basket.total());
+
+        basket.remove("A0002").unwrap();
+        basket.remove("A0002").unwrap();
+        assert!(basket.contents.lines.is_empty());
+        assert_eq!(
+            Err(BasketError::NotInBasket("A0002".to_string())),
+            basket.remove("A0002")
+        );
+    }
+
+    #[test]
+    fn test_set_quantity() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0001").unwrap();
+        basket.set_quantity("A0002", 4).unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        assert_eq!(
+            Ok(Money::new(1299 + 798, CurrencyCode::Eur)),
+            basket.total()
+        );
+
+        basket.set_quantity("A0001", 0).unwrap();
+        assert!(!basket.contents.lines.iter().any(|(sku, _)| sku == "A0001"));
+        assert_eq!(Ok(Money::new(798, CurrencyCode::Eur)), basket.total());
+
+        assert_eq!(
+            Err(BasketError::UnknownSku("A0009".to_string())),
+            basket.set_quantity("A0009", 1)
+        );
+    }
+
+    #[test]
+    fn test_void_line() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan_quantity("A0001", 2).unwrap();
+        basket.scan("A0002").unwrap();
+        basket.void_line("A0001").unwrap();
+
+        assert_eq!(Ok(Money::new(399, CurrencyCode::Eur)), basket.total());
+        assert_eq!(
+            Err(BasketError::NotInBasket("A0001".to_string())),
+            basket.void_line("A0001")
+        );
+    }
+
+    #[test]
+    fn test_total_overflow() {
+        let mut basket = Basket::new(bulk_catalog(u64::MAX / 2));
+
+        basket.scan_quantity("A0001", 3).unwrap();
+
+        assert_eq!(Err(BasketError::Overflow), basket.total());
+    }
+
+    fn taxed_basket(mode: PricingMode) -> Basket {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category,unit,tax\n\
+             A0001,Water,1299,EUR,,,reduced\n\
+             A0002,Soap,399,EUR,,,standard\n\
+             S0001,Stamp,85,EUR,,,exempt\n",
+        )
+        .unwrap();
+        let mut basket = Basket::new(Arc::new(catalog));
+
+        basket.set_tax_policy(TaxPolicy {
+            rates: TaxRates {
+                standard: Percentage::from_percent(20).unwrap(),
+                reduced: Percentage::from_percent(5).unwrap(),
+            },
+            mode,
+        });
+        basket.scan_quantity("A0002", 2).unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("S0001").unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        basket
+            .add_threshold_deal(ThresholdDeal {
+                threshold: Money::new(1500, CurrencyCode::Eur),
+                base: ThresholdBase::AfterDiscounts,
+                reward: ThresholdReward::AmountOff(Money::new(100, CurrencyCode::Eur)),
+            })
+            .unwrap();
+
+        basket
+    }
+
+    #[test]
+    fn test_tax_inclusive() {
+        let receipt = taxed_basket(PricingMode::Inclusive).receipt().unwrap();
+        let taxes: Vec<(Option<u32>, u64, u64)> = receipt
+            .taxes
+            .iter()
+            .map(|summary| {
+                let rate = summary.rate.map(u32::from);
+                (rate, summary.net.amount, summary.tax.amount)
+            })
+            .collect();
+
+        // The 1.00 off is split 23/73/4 between the lines, after the free soap.
+        assert_eq!(
+            vec![(Some(2000), 313, 63), (Some(500), 1168, 58), (None, 81, 0),],
+            taxes
+        );
+        assert_eq!(Money::new(1683, CurrencyCode::Eur), receipt.total);
+    }
+
+    #[test]
+    fn test_tax_exclusive() {
+        let receipt = taxed_basket(PricingMode::Exclusive).receipt().unwrap();
+        let taxes: Vec<(Option<u32>, u64, u64)> = receipt
+            .taxes
+            .iter()
+            .map(|summary| {
+                let rate = summary.rate.map(u32::from);
+                (rate, summary.net.amount, summary.tax.amount)
+            })
+            .collect();
+
+        assert_eq!(
+            vec![(Some(2000), 376, 75), (Some(500), 1226, 61), (None, 81, 0),],
+            taxes
+        );
+        assert_eq!(Money::new(1683 + 136, CurrencyCode::Eur), receipt.total);
+    }
+
+    #[test]
+    fn test_group_deal_lowers_tax_of_claimed_lines() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category,unit,tax\n\
+             A0001,Water,1299,EUR,,,reduced\n\
+             A0002,Soap,399,EUR,,,standard\n",
+        )
+        .unwrap();
+        let mut basket = Basket::new(Arc::new(catalog));
+
+        basket.set_tax_policy(TaxPolicy {
+            rates: TaxRates {
+                standard: Percentage::from_percent(20).unwrap(),
+                reduced: Percentage::from_percent(5).unwrap(),
+            },
+            mode: PricingMode::Inclusive,
+        });
+        basket.scan("A0001").unwrap();
+        basket.scan_quantity("A0002", 2).unwrap();
+        basket
+            .add_group_deal(GroupDeal {
+                kind: GroupDealKind::CheapestFree {
+                    group: ProductGroup::new("soap", ["A0002"]),
+                    quantity: 2,
+                },
+                priority: 0,
+            })
+            .unwrap();
+
+        let receipt = basket.receipt().unwrap();
+
+        // Only the soap is discounted, so the water keeps its full taxable amount.
+        assert_eq!(Money::new(399, CurrencyCode::Eur), receipt.taxes[0].gross);
+        assert_eq!(Money::new(1299, CurrencyCode::Eur), receipt.taxes[1].gross);
+    }
+
+    #[test]
+    fn test_jurisdiction_rates_follow_clock() {
+        let jurisdiction = TaxJurisdiction::from_json(
+            r#"{
+                "name": "Germany",
+                "pricing": "inclusive",
+                "rates": [
+                    { "class": "standard", "rate": 1900, "from": "2007-01-01" },
+                    { "class": "standard", "rate": 1600, "from": "2020-07-01", "until": "2020-12-31" },
+                    { "class": "reduced", "rate": 700, "from": "1983-07-01" }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let day = |date: &str| FixedClock(date.parse::<NaiveDate>().unwrap().into());
+        let mut basket = Basket::new(catalog());
+
+        basket.set_jurisdiction(Arc::new(jurisdiction));
+        basket.scan("A0001").unwrap();
+
+        basket.set_clock(day("2020-06-30"));
+        assert_eq!(
+            Money::new(207, CurrencyCode::Eur),
+            basket.receipt().unwrap().taxes[0].tax
+        );
+
+        basket.set_clock(day("2020-07-01"));
+        assert_eq!(
+            Money::new(179, CurrencyCode::Eur),
+            basket.receipt().unwrap().taxes[0].tax
+        );
+
+        basket.set_clock(day("2006-12-31"));
+        assert!(matches!(
+            basket.receipt(),
+            Err(BasketError::MissingTaxRate { .. })
+        ));
+    }
+
+    #[test]
+    fn test_deals_follow_clock() {
+        let happy_hour = Deal::builder("A0001")
+            .kind(DealKind::PercentageDiscount(
+                Percentage::from_percent(50).unwrap(),
+            ))
+            .validity(Validity {
+                start: Some("2024-03-01T00:00:00".parse().unwrap()),
+                end: None,
+                recurrence: Some(Recurrence {
+                    weekdays: vec![Weekday::Fri],
+                    from: NaiveTime::from_hms_opt(17, 0, 0).unwrap(),
+                    until: NaiveTime::from_hms_opt(19, 0, 0).unwrap(),
+                }),
+            })
+            .build()
+            .unwrap();
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0001").unwrap();
+        basket.add_deal(happy_hour).unwrap();
+
+        // 2024-03-08 is a Friday, a week after the deal started.
+        let mut total_at = |at: &str| {
+            basket.set_clock(FixedClock(at.parse().unwrap()));
+            basket.total().unwrap().amount
+        };
+
+        assert_eq!(649, total_at("2024-03-08T18:30:00"));
+        assert_eq!(1299, total_at("2024-03-08T19:00:00"));
+        assert_eq!(1299, total_at("2024-03-09T18:30:00"));
+        assert_eq!(1299, total_at("2024-02-23T18:30:00"));
+    }
+
+    #[test]
+    fn test_rule() {
+        let rule = |source| Rule::parse(source, CurrencyCode::Eur).unwrap();
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0001").unwrap();
+        basket.scan_quantity("A0002", 4).unwrap();
+        basket
+            .add_deal(Deal {
+                stacking: Stacking::Stackable,
+                ..buy1get1free()
+            })
+            .unwrap();
+        basket
+            .add_rule(rule(
+                r#"when sku in ("A0001", "A0002") and quantity >= 2 then 10% off"#,
+            ))
+            .unwrap();
+
+        let receipt = basket.receipt().unwrap();
+
+        // Only the soap is bought twice, and the rule takes 10% off what it costs after the
+        // stackable deal's free units.
+        assert_eq!(1, receipt.adjustments.len());
+        assert_eq!("10% off", receipt.adjustments[0].deal.to_string());
+        assert_eq!(
+            Money::new(80, CurrencyCode::Eur),
+            receipt.adjustments[0].amount
+        );
+        assert_eq!(
+            Money::new(1299 + 798 - 80, CurrencyCode::Eur),
+            receipt.total
+        );
+
+        assert_eq!(
+            Err(BasketError::DealReferencesMissingProduct(
+                "A0009".to_string()
+            )),
+            basket.add_rule(rule(r#"when sku = "A0009" then 10% off"#))
+        );
+        assert_eq!(
+            Err(BasketError::CurrencyMismatch {
+                expected: CurrencyCode::Eur,
+                found: CurrencyCode::Usd,
+            }),
+            basket.add_rule(
+                Rule::parse("when quantity > 1 then 1.00 off", CurrencyCode::Usd).unwrap()
+            )
+        );
+
+        let mut invalid = rule("when quantity >= 1 then buy 1 get 1 free");
+        invalid.action = DealKind::BuyNGetMFree { buy: 0, free: 1 };
+        assert_eq!(
+            Err(BasketError::InvalidDeal(DealError::ZeroQuantity("buy"))),
+            basket.add_rule(invalid)
+        );
+        assert_eq!(
+            Money::new(1299 + 798 - 80, CurrencyCode::Eur),
+            basket.total().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rule_skips_units_claimed_by_group_deal() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan_quantity("A0002", 3).unwrap();
+        basket
+            .add_group_deal(GroupDeal {
+                kind: GroupDealKind::CheapestFree {
+                    group: ProductGroup::new("soap", ["A0002"]),
+                    quantity: 3,
+                },
+                priority: 0,
+            })
+            .unwrap();
+        basket
+            .add_rule(
+                Rule::parse(
+                    r#"when sku = "A0002" then buy 2 get 1 free"#,
+                    CurrencyCode::Eur,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let receipt = basket.receipt().unwrap();
+
+        assert_eq!(1, receipt.adjustments.len());
+        assert_eq!(Money::new(798, CurrencyCode::Eur), receipt.total);
+    }
+
+    #[test]
+    fn test_rule_does_not_stack_on_exclusive_deal() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan_quantity("A0002", 3).unwrap();
+        basket
+            .add_deal(
+                Deal::builder("A0002")
+                    .kind(DealKind::BuyNGetMFree { buy: 2, free: 1 })
+                    .stacking(Stacking::Exclusive)
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        basket
+            .add_rule(
+                Rule::parse(
+                    r#"when sku = "A0002" then buy 2 get 1 free"#,
+                    CurrencyCode::Eur,
+                )
+                .unwrap(),
+            )
+            .unwrap();
+
+        let receipt = basket.receipt().unwrap();
+
+        assert!(receipt.adjustments.is_empty());
+        assert_eq!(Money::new(798, CurrencyCode::Eur), receipt.total);
+    }
+
+    #[test]
+    fn test_rules_follow_clock() {
+        let mut rule = Rule::parse("when quantity >= 1 then 50% off", CurrencyCode::Eur).unwrap();
+        rule.validity = Validity {
+            start: Some("2024-03-04T00:00:00".parse().unwrap()),
+            end: Some("2024-03-11T00:00:00".parse().unwrap()),
+            recurrence: None,
+        };
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0001").unwrap();
+        basket.add_rule(rule.clone()).unwrap();
+
+        let mut total_at = |at: &str| {
+            basket.set_clock(FixedClock(at.parse().unwrap()));
+            basket.total().unwrap().amount
+        };
+
+        assert_eq!(649, total_at("2024-03-08T12:00:00"));
+        assert_eq!(1299, total_at("2024-03-11T00:00:00"));
+
+        rule.validity.end = rule.validity.start;
+        assert_eq!(
+            Err(BasketError::InvalidDeal(DealError::EmptyValidity)),
+            basket.add_rule(rule)
+        );
+    }
+
+    #[test]
+    fn test_total_in() {
+        let catalog = catalog();
+        let rates = ExchangeRates::from_csv("from,to,rate\nEUR,USD,1.0843\n").unwrap();
+        let mut basket = Basket::new(Arc::clone(&catalog));
+
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+
+        let total = basket.total_in(CurrencyCode::Usd, &rates).unwrap();
+
+        assert_eq!(Money::new(1698, CurrencyCode::Eur), total.total);
+        assert_eq!(Money::new(1841, CurrencyCode::Usd), total.converted);
+        assert_eq!("1 EUR = 1.0843 USD", total.rate.to_string());
+
+        assert_eq!(
+            Err(BasketError::MissingExchangeRate {
+                from: CurrencyCode::Eur,
+                to: CurrencyCode::Gbp
+            }),
+            basket.total_in(CurrencyCode::Gbp, &rates)
+        );
+    }
+
+    fn bulk_catalog(price: u64) -> Arc<Catalog> {
+        Arc::new(
+            Catalog::from_csv(&format!(
+                "sku,name,price,currency\nA0001,Bulk,{price},EUR\n"
+            ))
+            .unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_amount_off_above_free_price() {
+        let mut basket = Basket::new(bulk_catalog(0));
+        let deal = Deal::builder("A0001")
+            .kind(DealKind::FixedAmountOff {
+                amount: Money::new(1 << 63, CurrencyCode::Eur),
+            })
+            .build()
+            .unwrap();
+
+        // Found by `prop_deals_never_overflow_silently`: the amount off two units overflows.
+        basket.scan_quantity("A0001", 2).unwrap();
+        basket.add_deal(deal).unwrap();
+
+        assert_eq!(Ok(Money::new(0, CurrencyCode::Eur)), basket.total());
+    }
+
+    fn deal_kind() -> impl Strategy<Value = DealKind> {
+        prop_oneof![
+            Just(DealKind::Buy1Get1Free),
+            (0..=10_000u32).prop_map(|basis_points| {
+                DealKind::PercentageDiscount(Percentage::from_basis_points(basis_points).unwrap())
+            }),
+            (1..10u32, 1..10u32).prop_map(|(buy, free)| DealKind::BuyNGetMFree { buy, free }),
+            (1..10u32, any::<u64>()).prop_map(|(quantity, price)| {
+                DealKind::MultiBuyFixedPrice {
+                    quantity,
+                    price: Money::new(price, CurrencyCode::Eur),
+                }
+            }),
+            any::<u64>().prop_map(|amount| DealKind::FixedAmountOff {
+                amount: Money::new(amount, CurrencyCode::Eur),
+            }),
+        ]
+    }
+
+    fn quantity() -> impl Strategy<Value = u32> {
+        prop_oneof![1..1000u32, 1..=u32::MAX]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_total_is_exact_or_overflows(price: u64, quantity in quantity()) {
+            let catalog = bulk_catalog(price);
+            let mut basket = Basket::new(Arc::clone(&catalog));
+
+            basket.scan_quantity("A0001", quantity).unwrap();
+
+            let expected = price
+                .checked_mul(u64::from(quantity))
+                .map(|amount| Money::new(amount, CurrencyCode::Eur))
+                .ok_or(BasketError::Overflow);
+            prop_assert_eq!(expected, basket.total());
+        }
+
+        #[test]
+        fn prop_deals_never_overflow_silently(
+            price: u64,
+            quantity in quantity(),
+            kind in deal_kind(),
+        ) {
+            let catalog = bulk_catalog(price);
+            let deal = Deal {
+                product: "A0001".to_string(),
+                kind,
+                stacking: Stacking::Exclusive,
+                priority: 0,
+                validity: Validity::default(),
+            };
+            let mut basket = Basket::new(Arc::clone(&catalog));
+
+            basket.scan_quantity("A0001", quantity).unwrap();
+            basket.add_deal(deal).unwrap();
+
+            match price.checked_mul(u64::from(quantity)) {
+                Some(gross) => prop_assert!(basket.total().unwrap().amount <= gross),
+                None => prop_assert_eq!(Err(BasketError::Overflow), basket.total()),
+            }
+        }
+    }
+}
@@ -0,0 +1,558 @@
+//! The products that can be sold and how they are priced.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Display,
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    money::{CurrencyCode, Money, MoneyError, RoundingMode},
+    tax::TaxClass,
+};
+
+/// The set of products that can be scanned, keyed by SKU.
+///
+/// All products in a catalog are priced in the same currency.
+#[derive(Debug, Default)]
+pub struct Catalog {
+    currency: CurrencyCode,
+    products: HashMap<String, Product>,
+}
+
+/// A product that can be scanned, as listed in a [`Catalog`].
+#[derive(Debug, Hash, Eq, PartialEq)]
+pub struct Product {
+    pub sku: String,
+    pub name: String,
+    /// The price per `unit`.
+    pub price: Money,
+    /// The category the product can be grouped by, see [`Catalog::category`].
+    pub category: Option<String>,
+    pub unit: Unit,
+    pub tax_class: TaxClass,
+}
+
+impl Product {
+    /// Creates a product sold per `unit` at `price`.
+    pub fn new(
+        sku: String,
+        name: String,
+        price: Money,
+        category: Option<String>,
+        unit: Unit,
+        tax_class: TaxClass,
+    ) -> Self {
+        Self {
+            sku,
+            name,
+            price,
+            category,
+            unit,
+            tax_class,
+        }
+    }
+}
+
+/// A named set of SKUs that a deal can target as a whole.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProductGroup {
+    pub name: String,
+    pub skus: BTreeSet<String>,
+}
+
+/// How a product is sold, which also decides what a basket quantity of the product counts.
+#[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq)]
+pub enum Unit {
+    /// Priced per piece; quantities count pieces.
+    #[default]
+    Piece,
+    /// Priced per kilogram; quantities are weights in grams.
+    Kilogram,
+    /// Priced per litre; quantities are volumes in millilitres.
+    Litre,
+}
+
+/// Why a catalog could not be loaded.
+#[derive(Debug)]
+pub enum CatalogError {
+    Io(std::io::Error),
+    UnsupportedFormat(String),
+    Invalid { line: usize, reason: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct Record {
+    sku: String,
+    name: String,
+    price: u64,
+    currency: String,
+    #[serde(default)]
+    category: Option<String>,
+    #[serde(default)]
+    unit: Option<String>,
+    #[serde(default)]
+    tax: Option<String>,
+}
+
+impl Unit {
+    /// How many steps of a basket quantity make up one unit the price is given for.
+    pub fn scale(self) -> u32 {
+        match self {
+            Unit::Piece => 1,
+            Unit::Kilogram | Unit::Litre => 1000,
+        }
+    }
+
+    /// Whether quantities of the product are weights or volumes rather than pieces.
+    pub fn is_measured(self) -> bool {
+        self != Unit::Piece
+    }
+
+    /// Prices `quantity` at `price` per unit, rounding half up to the nearest minor unit,
+    /// e.g. 250 grams at 1.99 per kg cost 0.50.
+    pub fn price(self, price: Money, quantity: u32) -> Result<Money, MoneyError> {
+        price.checked_mul_div_rounded(quantity, self.scale(), RoundingMode::HalfUp)
+    }
+}
+
+impl Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Unit::Piece => "piece",
+            Unit::Kilogram => "kg",
+            Unit::Litre => "l",
+        })
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    fn from_str(unit: &str) -> Result<Self, Self::Err> {
+        match unit {
+            "piece" => Ok(Unit::Piece),
+            "kg" => Ok(Unit::Kilogram),
+            "l" => Ok(Unit::Litre),
+            _ => Err(format!("unknown unit '{unit}', expected piece, kg or l")),
+        }
+    }
+}
+
+impl Display for CatalogError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CatalogError::Io(error) => write!(f, "could not read catalog: {error}"),
+            CatalogError::UnsupportedFormat(extension) => {
+                write!(
+                    f,
+                    "unsupported catalog format '{extension}', expected csv or json"
+                )
+            }
+            CatalogError::Invalid { line, reason } => write!(f, "line {line}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for CatalogError {}
+
+impl From<std::io::Error> for CatalogError {
+    fn from(error: std::io::Error) -> Self {
+        CatalogError::Io(error)
+    }
+}
+
+impl Catalog {
+    /// Loads a catalog from a `.csv` or `.json` file, picking the format by extension.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, CatalogError> {
+        let path = path.as_ref();
+        let input = fs::read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("csv") => Self::from_csv(&input),
+            Some("json") => Self::from_json(&input),
+            other => Err(CatalogError::UnsupportedFormat(
+                other.unwrap_or_default().to_string(),
+            )),
+        }
+    }
+
+    /// Parses `sku,name,price,currency` rows, where the price is given in minor units.
+    ///
+    /// An optional fifth `category` column assigns products to categories; leave it empty for
+    /// products without one. An optional sixth `unit` column sells a product per `kg` or `l`
+    /// instead of per `piece`, the default when it is empty. An optional seventh `tax` column
+    /// gives the product's [`TaxClass`], `standard` when it is empty. The header row is
+    /// required. Blank lines and lines starting with `#` are skipped.
+    pub fn from_csv(input: &str) -> Result<Self, CatalogError> {
+        let mut lines = input
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        let columns = match lines.next() {
+            Some((_, "sku,name,price,currency")) => 4,
+            Some((_, "sku,name,price,currency,category")) => 5,
+            Some((_, "sku,name,price,currency,category,unit")) => 6,
+            Some((_, "sku,name,price,currency,category,unit,tax")) => 7,
+            Some((line, header)) => {
+                return Err(CatalogError::Invalid {
+                    line,
+                    reason: format!(
+                        "expected header 'sku,name,price,currency[,category[,unit[,tax]]]', \
+                         found '{header}'"
+                    ),
+                })
+            }
+            None => return Ok(Self::default()),
+        };
+
+        let mut catalog = Self::default();
+
+        for (line, row) in lines {
+            let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+
+            let (sku, name, price, currency, category, unit, tax) = match fields[..] {
+                [sku, name, price, currency] if columns == 4 => {
+                    (sku, name, price, currency, "", "", "")
+                }
+                [sku, name, price, currency, category] if columns == 5 => {
+                    (sku, name, price, currency, category, "", "")
+                }
+                [sku, name, price, currency, category, unit] if columns == 6 => {
+                    (sku, name, price, currency, category, unit, "")
+                }
+                [sku, name, price, currency, category, unit, tax] if columns == 7 => {
+                    (sku, name, price, currency, category, unit, tax)
+                }
+                _ => {
+                    return Err(CatalogError::Invalid {
+                        line,
+                        reason: format!("expected {columns} fields, found {}", fields.len()),
+                    })
+                }
+            };
+
+            let price = price.parse().map_err(|_| CatalogError::Invalid {
+                line,
+                reason: format!("invalid price '{price}'"),
+            })?;
+
+            catalog.insert(
+                line,
+                Record {
+                    sku: sku.to_string(),
+                    name: name.to_string(),
+                    price,
+                    currency: currency.to_string(),
+                    category: (!category.is_empty()).then(|| category.to_string()),
+                    unit: (!unit.is_empty()).then(|| unit.to_string()),
+                    tax: (!tax.is_empty()).then(|| tax.to_string()),
+                },
+            )?;
+        }
+
+        Ok(catalog)
+    }
+
+    /// Parses a JSON array of `{ "sku", "name", "price", "currency" }` objects, with prices in
+    /// minor units and an optional `"category"`, `"unit"` and `"tax"` class.
+    pub fn from_json(input: &str) -> Result<Self, CatalogError> {
+        let mut deserializer = serde_json::Deserializer::from_str(input);
+        let records: Vec<serde_json::Value> = Vec::deserialize(&mut deserializer)
+            .and_then(|records| deserializer.end().map(|_| records))
+            .map_err(|error| CatalogError::Invalid {
+                line: error.line(),
+                reason: error.to_string(),
+            })?;
+
+        let mut catalog = Self::default();
+
+        for (index, value) in records.into_iter().enumerate() {
+            let line = json_entry_line(input, index);
+
+            let record = Record::deserialize(value).map_err(|error| CatalogError::Invalid {
+                line,
+                reason: error.to_string(),
+            })?;
+
+            catalog.insert(line, record)?;
+        }
+
+        Ok(catalog)
+    }
+
+    /// The currency every product in the catalog is priced in.
+    pub fn currency(&self) -> CurrencyCode {
+        self.currency
+    }
+
+    /// Looks up the product with the given SKU.
+    pub fn get(&self, sku: &str) -> Option<&Product> {
+        self.products.get(sku)
+    }
+
+    /// Collects every product in `category` into a group.
+    pub fn category(&self, category: &str) -> ProductGroup {
+        ProductGroup::new(
+            category,
+            self.products
+                .values()
+                .filter(|product| product.category.as_deref() == Some(category))
+                .map(|product| product.sku.as_str()),
+        )
+    }
+
+    fn insert(&mut self, line: usize, record: Record) -> Result<(), CatalogError> {
+        if record.sku.is_empty() {
+            return Err(CatalogError::Invalid {
+                line,
+                reason: "empty sku".to_string(),
+            });
+        }
+
+        if self.products.contains_key(&record.sku) {
+            return Err(CatalogError::Invalid {
+                line,
+                reason: format!("duplicate sku '{}'", record.sku),
+            });
+        }
+
+        let currency: CurrencyCode = record
+            .currency
+            .parse()
+            .map_err(|reason| CatalogError::Invalid { line, reason })?;
+        let unit = match record.unit {
+            Some(unit) => unit
+                .parse()
+                .map_err(|reason| CatalogError::Invalid { line, reason })?,
+            None => Unit::Piece,
+        };
+        let tax_class = match record.tax {
+            Some(tax) => tax
+                .parse()
+                .map_err(|reason| CatalogError::Invalid { line, reason })?,
+            None => TaxClass::Standard,
+        };
+
+        if self.products.is_empty() {
+            self.currency = currency;
+        } else if currency != self.currency {
+            return Err(CatalogError::Invalid {
+                line,
+                reason: format!(
+                    "currency {currency} does not match catalog currency {}",
+                    self.currency
+                ),
+            });
+        }
+
+        self.products.insert(
+            record.sku.clone(),
+            Product::new(
+                record.sku,
+                record.name,
+                Money::new(record.price, currency),
+                record.category,
+                unit,
+                tax_class,
+            ),
+        );
+
+        Ok(())
+    }
+}
+
+impl ProductGroup {
+    /// Creates a group called `name` that contains `skus`.
+    pub fn new<'s>(name: &str, skus: impl IntoIterator<Item = &'s str>) -> Self {
+        Self {
+            name: name.to_string(),
+            skus: skus.into_iter().map(str::to_string).collect(),
+        }
+    }
+
+    /// Whether `sku` is in the group.
+    pub fn contains(&self, sku: &str) -> bool {
+        self.skus.contains(sku)
+    }
+}
+
+/// Finds the line on which the `index`-th element of the top-level JSON array starts.
+pub(crate) fn json_entry_line(input: &str, index: usize) -> usize {
+    let mut depth = 0;
+    let mut entry = 0;
+    let mut line = 1;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for c in input.chars() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '\n' => line += 1,
+            ',' if depth == 1 => entry += 1,
+            c if depth == 1 && entry == index && !c.is_whitespace() => return line,
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => depth -= 1,
+            _ => {}
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::{Catalog, CatalogError, Unit},
+        tax::TaxClass,
+    };
+
+    #[test]
+    fn test_from_csv() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency\nA0001,Water,1299,EUR\n\nA0002,Soap,399,EUR\n",
+        )
+        .unwrap();
+
+        assert_eq!(1299, catalog.get("A0001").unwrap().price.amount);
+        assert_eq!("Soap", catalog.get("A0002").unwrap().name);
+        assert!(catalog.get("A0003").is_none());
+    }
+
+    #[test]
+    fn test_from_csv_reports_line() {
+        let error = Catalog::from_csv(
+            "sku,name,price,currency\nA0001,Water,1299,EUR\nA0002,Soap,3.99,EUR\n",
+        )
+        .unwrap_err();
+
+        assert!(matches!(error, CatalogError::Invalid { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_from_csv_rejects_duplicate_sku() {
+        let error = Catalog::from_csv(
+            "sku,name,price,currency\nA0001,Water,1299,EUR\nA0001,Soap,399,EUR\n",
+        )
+        .unwrap_err();
+
+        assert_eq!("line 3: duplicate sku 'A0001'", error.to_string());
+    }
+
+    #[test]
+    fn test_from_json() {
+        let catalog = Catalog::from_json(
+            r#"[
+                { "sku": "A0001", "name": "Water", "price": 1299, "currency": "EUR" },
+                { "sku": "A0002", "name": "Soap", "price": 399, "currency": "EUR" }
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(399, catalog.get("A0002").unwrap().price.amount);
+    }
+
+    #[test]
+    fn test_from_json_reports_line() {
+        let error = Catalog::from_json(
+            r#"[
+                { "sku": "A0001", "name": "Water", "price": 1299, "currency": "EUR" },
+                { "sku": "A0002", "name": "Soap", "currency": "EUR" }
+            ]"#,
+        )
+        .unwrap_err();
+
+        assert!(matches!(error, CatalogError::Invalid { line: 3, .. }));
+    }
+
+    #[test]
+    fn test_from_csv_rejects_mixed_currencies() {
+        let error = Catalog::from_csv(
+            "sku,name,price,currency\nA0001,Water,1299,EUR\nA0002,Soap,399,USD\n",
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            "line 3: currency USD does not match catalog currency EUR",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_category() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category\n\
+             S1,Shampoo,499,EUR,haircare\n\
+             C1,Conditioner,399,EUR,haircare\n\
+             A0001,Water,1299,EUR,\n",
+        )
+        .unwrap();
+
+        let haircare = catalog.category("haircare");
+
+        assert!(haircare.contains("S1") && haircare.contains("C1"));
+        assert!(!haircare.contains("A0001"));
+        assert_eq!(None, catalog.get("A0001").unwrap().category);
+    }
+
+    #[test]
+    fn test_units() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category,unit\n\
+             B1,Bananas,199,EUR,fruit,kg\n\
+             M1,Milk,129,EUR,,l\n\
+             A0001,Water,1299,EUR,,\n",
+        )
+        .unwrap();
+
+        assert_eq!(Unit::Kilogram, catalog.get("B1").unwrap().unit);
+        assert_eq!(Unit::Litre, catalog.get("M1").unwrap().unit);
+        assert_eq!(Unit::Piece, catalog.get("A0001").unwrap().unit);
+
+        let error = Catalog::from_json(
+            r#"[{ "sku": "B1", "name": "Bananas", "price": 199, "currency": "EUR", "unit": "lb" }]"#,
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            "line 1: unknown unit 'lb', expected piece, kg or l",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_tax_classes() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category,unit,tax\n\
+             B1,Bananas,199,EUR,fruit,kg,reduced\n\
+             A0001,Water,1299,EUR,,,\n",
+        )
+        .unwrap();
+
+        assert_eq!(TaxClass::Reduced, catalog.get("B1").unwrap().tax_class);
+        assert_eq!(TaxClass::Standard, catalog.get("A0001").unwrap().tax_class);
+
+        let catalog = Catalog::from_json(
+            r#"[{ "sku": "S1", "name": "Stamps", "price": 85, "currency": "EUR", "tax": "exempt" }]"#,
+        )
+        .unwrap();
+
+        assert_eq!(TaxClass::Exempt, catalog.get("S1").unwrap().tax_class);
+    }
+}
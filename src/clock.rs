@@ -0,0 +1,49 @@
+//! Where a basket gets the current date and time from.
+
+use std::{
+    fmt::Debug,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{Local, NaiveDateTime};
+
+/// A source of the current date and time, in the local time of the store.
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+
+    /// The current time in milliseconds since the Unix epoch, as recorded in event logs. By
+    /// default the local time is read as if it were UTC.
+    fn timestamp(&self) -> u64 {
+        self.now()
+            .and_utc()
+            .timestamp_millis()
+            .try_into()
+            .unwrap_or(0)
+    }
+}
+
+/// The system clock, in the local time zone of the machine.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+/// A clock that always reads the same time, e.g. to price a basket as of a given date.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FixedClock(pub NaiveDateTime);
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Local::now().naive_local()
+    }
+
+    fn timestamp(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64)
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
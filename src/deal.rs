@@ -0,0 +1,809 @@
+//! Deals on a single product and how they round discounted prices.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    basket::BasketError,
+    catalog::Unit,
+    money::{CurrencyFormat, Money, MoneyError, RoundingMode},
+    validity::Validity,
+};
+
+/// A deal on a single product, identified by its SKU.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Deal {
+    pub product: String,
+    pub kind: DealKind,
+    pub stacking: Stacking,
+    /// Lower values take precedence over higher ones.
+    pub priority: u32,
+    /// When the deal is on offer.
+    #[serde(default, skip_serializing_if = "Validity::is_always")]
+    pub validity: Validity,
+}
+
+/// How a [`Deal`] lowers the price of its product.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DealKind {
+    Buy1Get1Free,
+    PercentageDiscount(Percentage),
+    /// For every `buy` units paid for, the next `free` units are free.
+    BuyNGetMFree {
+        buy: u32,
+        free: u32,
+    },
+    /// Every full group of `quantity` units costs `price`; leftover units are charged as usual.
+    MultiBuyFixedPrice {
+        quantity: u32,
+        price: Money,
+    },
+    /// Takes `amount` off every unit, never going below zero.
+    FixedAmountOff {
+        amount: Money,
+    },
+}
+
+/// A percentage between 0% and 100%, held in basis points so that e.g. 12.5% is exact.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "u32", into = "u32")]
+pub struct Percentage {
+    basis_points: u32,
+}
+
+/// Builds a [`Deal`], checking that its configuration makes sense.
+#[derive(Debug)]
+pub struct DealBuilder {
+    product: String,
+    kind: Option<DealKind>,
+    stacking: Stacking,
+    priority: u32,
+    validity: Validity,
+}
+
+/// Why a deal could not be built.
+#[derive(Debug, PartialEq)]
+pub enum DealError {
+    /// A percentage above 100%, in basis points.
+    PercentageOutOfRange(u32),
+    EmptyProduct,
+    MissingKind,
+    /// A deal parameter that must be at least 1 was 0.
+    ZeroQuantity(&'static str),
+    /// The deal is never on offer.
+    EmptyValidity,
+    /// The deal would give away every unit it applies to.
+    EveryUnitFree,
+}
+
+/// Whether a deal may be combined with other deals on the same product.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stacking {
+    /// The deal is applied on its own, or not at all.
+    Exclusive,
+    /// The deal is applied together with every other stackable deal on the product.
+    Stackable,
+}
+
+/// How percentage discounts round the discounted price to a whole minor unit.
+///
+/// The default rounds the whole line down, in the store's favour.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rounding {
+    pub mode: RoundingMode,
+    pub scope: RoundingScope,
+}
+
+/// Which amount a percentage discount is rounded on.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RoundingScope {
+    /// The discounted unit price is rounded and then multiplied by the quantity.
+    PerUnit,
+    /// The discounted line amount is rounded once.
+    #[default]
+    PerLine,
+}
+
+impl DealKind {
+    /// Whether the deal is defined in terms of whole units, which makes it meaningless for
+    /// products sold by weight or volume.
+    pub fn counts_units(&self) -> bool {
+        match self {
+            DealKind::Buy1Get1Free
+            | DealKind::BuyNGetMFree { .. }
+            | DealKind::MultiBuyFixedPrice { .. } => true,
+            DealKind::PercentageDiscount(_) | DealKind::FixedAmountOff { .. } => false,
+        }
+    }
+
+    /// Describes the deal as it is shown on a receipt, formatting amounts according to
+    /// `format`, e.g. `3 for €10.00`.
+    pub fn describe(&self, format: &CurrencyFormat) -> String {
+        match self {
+            DealKind::Buy1Get1Free => "Buy 1 get 1 free".to_string(),
+            DealKind::PercentageDiscount(percentage) => format!("{percentage} off"),
+            DealKind::BuyNGetMFree { buy, free } => format!("Buy {buy} get {free} free"),
+            DealKind::MultiBuyFixedPrice { quantity, price } => {
+                format!("{quantity} for {}", price.display_with(format))
+            }
+            DealKind::FixedAmountOff { amount } => {
+                format!("{} off", amount.display_with(format))
+            }
+        }
+    }
+
+    /// Checks that a deal of this kind can be applied, i.e. that it never asks for zero units.
+    pub fn validate(&self) -> Result<(), DealError> {
+        match *self {
+            DealKind::BuyNGetMFree { buy: 0, .. } => Err(DealError::ZeroQuantity("buy")),
+            DealKind::BuyNGetMFree { free: 0, .. } => Err(DealError::ZeroQuantity("free")),
+            DealKind::MultiBuyFixedPrice { quantity: 0, .. } => {
+                Err(DealError::ZeroQuantity("quantity"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The amount of money the deal is defined in, if it has one.
+    pub fn amount(&self) -> Option<Money> {
+        match *self {
+            DealKind::MultiBuyFixedPrice { price, .. } => Some(price),
+            DealKind::FixedAmountOff { amount } => Some(amount),
+            DealKind::Buy1Get1Free
+            | DealKind::PercentageDiscount(_)
+            | DealKind::BuyNGetMFree { .. } => None,
+        }
+    }
+
+    /// Applies a deal of this kind to the current price of a line of `quantity` units.
+    ///
+    /// Deals work on the current line amount rather than the catalog price, so they can be
+    /// chained on the result of a previous deal. Units charged at the current price are
+    /// charged proportionally, rounding down, except for percentage discounts which are
+    /// rounded according to `rounding`. A deal never makes a line more expensive.
+    pub fn apply(
+        &self,
+        amount: Money,
+        quantity: u32,
+        rounding: Rounding,
+    ) -> Result<Money, BasketError> {
+        match *self {
+            DealKind::Buy1Get1Free => Ok(amount.checked_mul_div(quantity.div_ceil(2), quantity)?),
+            DealKind::BuyNGetMFree { buy, free } => {
+                let free_units = buy
+                    .checked_add(free)
+                    .and_then(|size| quantity.checked_div(size))
+                    .map_or(0, |groups| groups * free);
+
+                Ok(amount.checked_mul_div(quantity - free_units, quantity)?)
+            }
+            DealKind::MultiBuyFixedPrice {
+                quantity: size,
+                price,
+            } => {
+                let groups = quantity.checked_div(size).unwrap_or(0);
+                let leftover = amount.checked_mul_div(quantity - groups * size, quantity)?;
+                let discounted = price.saturating_mul(groups).checked_add(leftover);
+
+                match discounted {
+                    Ok(discounted) if discounted.amount < amount.amount => Ok(discounted),
+                    // A fixed price too large to represent is never cheaper.
+                    Ok(_) | Err(MoneyError::Overflow) => Ok(amount),
+                    Err(error) => Err(error.into()),
+                }
+            }
+            DealKind::FixedAmountOff { amount: off } => {
+                Ok(amount.saturating_sub(off.saturating_mul(quantity))?)
+            }
+            DealKind::PercentageDiscount(percentage) => Ok(match rounding.scope {
+                RoundingScope::PerUnit => {
+                    percentage.take_off_per(amount, quantity, rounding.mode)?
+                }
+                RoundingScope::PerLine => percentage.take_off(amount, rounding.mode)?,
+            }),
+        }
+    }
+
+    /// Applies a deal of this kind to the current price of a line of a product sold per
+    /// `unit`, where `quantity` is the weight or volume on the line.
+    ///
+    /// Percentage discounts are always rounded on the whole line, and a fixed amount is taken
+    /// off per kilogram or litre, prorated to the quantity and rounded half up. Deals that
+    /// [count units](DealKind::counts_units) leave the line unchanged.
+    pub fn apply_measured(
+        &self,
+        amount: Money,
+        quantity: u32,
+        unit: Unit,
+        rounding: Rounding,
+    ) -> Result<Money, BasketError> {
+        match *self {
+            DealKind::PercentageDiscount(percentage) => {
+                Ok(percentage.take_off(amount, rounding.mode)?)
+            }
+            DealKind::FixedAmountOff { amount: off } => {
+                let off = match unit.price(off, quantity) {
+                    Err(MoneyError::Overflow) => Money::new(u64::MAX, off.currency),
+                    off => off?,
+                };
+
+                Ok(amount.saturating_sub(off)?)
+            }
+            DealKind::Buy1Get1Free
+            | DealKind::BuyNGetMFree { .. }
+            | DealKind::MultiBuyFixedPrice { .. } => Ok(amount),
+        }
+    }
+}
+
+impl Percentage {
+    const MAX_BASIS_POINTS: u32 = 10_000;
+
+    pub const ZERO: Percentage = Percentage { basis_points: 0 };
+
+    /// Creates a percentage from hundredths of a percent, e.g. 1250 for 12.5%.
+    pub fn from_basis_points(basis_points: u32) -> Result<Self, DealError> {
+        if basis_points > Self::MAX_BASIS_POINTS {
+            return Err(DealError::PercentageOutOfRange(basis_points));
+        }
+
+        Ok(Percentage { basis_points })
+    }
+
+    /// Creates a percentage from whole percents.
+    pub fn from_percent(percent: u32) -> Result<Self, DealError> {
+        Self::from_basis_points(percent.saturating_mul(100))
+    }
+
+    /// Multiplies `amount` by what is left after taking the percentage off, rounding to a
+    /// minor unit with `mode`.
+    pub fn take_off(self, amount: Money, mode: RoundingMode) -> Result<Money, MoneyError> {
+        self.take_off_per(amount, 1, mode)
+    }
+
+    /// Like [`Percentage::take_off`], but rounds `amount / count` and multiplies the result
+    /// back by `count`, so that every one of `count` units is rounded on its own.
+    pub fn take_off_per(
+        self,
+        amount: Money,
+        count: u32,
+        mode: RoundingMode,
+    ) -> Result<Money, MoneyError> {
+        let denominator = Self::MAX_BASIS_POINTS
+            .checked_mul(count)
+            .ok_or(MoneyError::Overflow)?;
+
+        amount
+            .checked_mul_div_rounded(
+                Self::MAX_BASIS_POINTS - self.basis_points,
+                denominator,
+                mode,
+            )?
+            .checked_mul(count)
+    }
+}
+
+impl TryFrom<u32> for Percentage {
+    type Error = DealError;
+
+    fn try_from(basis_points: u32) -> Result<Self, Self::Error> {
+        Self::from_basis_points(basis_points)
+    }
+}
+
+impl From<Percentage> for u32 {
+    fn from(percentage: Percentage) -> Self {
+        percentage.basis_points
+    }
+}
+
+impl Display for Percentage {
+    /// Formats the percentage without trailing zeros, e.g. `10%` or `12.5%`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (whole, fraction) = (self.basis_points / 100, self.basis_points % 100);
+
+        match fraction {
+            0 => write!(f, "{whole}%"),
+            fraction if fraction % 10 == 0 => write!(f, "{whole}.{}%", fraction / 10),
+            fraction => write!(f, "{whole}.{fraction:02}%"),
+        }
+    }
+}
+
+impl Display for DealError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DealError::PercentageOutOfRange(basis_points) => {
+                write!(f, "percentage of {basis_points} basis points is above 100%")
+            }
+            DealError::EmptyProduct => f.write_str("deal does not name a product"),
+            DealError::MissingKind => f.write_str("deal does not have a kind"),
+            DealError::ZeroQuantity(field) => {
+                write!(f, "deal parameter '{field}' must be at least 1")
+            }
+            DealError::EmptyValidity => f.write_str("deal is never valid"),
+            DealError::EveryUnitFree => f.write_str("deal would make every unit free"),
+        }
+    }
+}
+
+impl std::error::Error for DealError {}
+
+impl FromStr for Rounding {
+    type Err = String;
+
+    /// Parses a rounding mode such as `half-even`, optionally followed by `-per-unit` or
+    /// `-per-line`. Rounding is per line unless stated otherwise.
+    fn from_str(rounding: &str) -> Result<Self, Self::Err> {
+        let (mode, scope) = if let Some(mode) = rounding.strip_suffix("-per-unit") {
+            (mode, RoundingScope::PerUnit)
+        } else if let Some(mode) = rounding.strip_suffix("-per-line") {
+            (mode, RoundingScope::PerLine)
+        } else {
+            (rounding, RoundingScope::PerLine)
+        };
+
+        Ok(Rounding {
+            mode: mode.parse()?,
+            scope,
+        })
+    }
+}
+
+impl Deal {
+    /// Starts building an exclusive deal with priority 0 on `product` that is always valid.
+    pub fn builder(product: impl Into<String>) -> DealBuilder {
+        DealBuilder {
+            product: product.into(),
+            kind: None,
+            stacking: Stacking::Exclusive,
+            priority: 0,
+            validity: Validity::default(),
+        }
+    }
+
+    /// Returns the first problem found with the configuration of the deal. Deals built with
+    /// [`Deal::builder`] have already been checked.
+    pub fn validate(&self) -> Result<(), DealError> {
+        if self.product.is_empty() {
+            return Err(DealError::EmptyProduct);
+        }
+
+        self.kind.validate()?;
+        if self.validity.is_empty() {
+            return Err(DealError::EmptyValidity);
+        }
+
+        Ok(())
+    }
+
+    /// Applies the deal to the current price of a line of `quantity` units, see
+    /// [`DealKind::apply`].
+    pub fn apply(
+        &self,
+        amount: Money,
+        quantity: u32,
+        rounding: Rounding,
+    ) -> Result<Money, BasketError> {
+        self.kind.apply(amount, quantity, rounding)
+    }
+
+    /// Applies the deal to a line of a product sold per `unit`, see
+    /// [`DealKind::apply_measured`].
+    pub fn apply_measured(
+        &self,
+        amount: Money,
+        quantity: u32,
+        unit: Unit,
+        rounding: Rounding,
+    ) -> Result<Money, BasketError> {
+        self.kind.apply_measured(amount, quantity, unit, rounding)
+    }
+}
+
+impl DealBuilder {
+    /// Sets what the deal does. Every deal needs a kind.
+    pub fn kind(mut self, kind: DealKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Sets whether the deal can be combined with other deals on the same product.
+    pub fn stacking(mut self, stacking: Stacking) -> Self {
+        self.stacking = stacking;
+        self
+    }
+
+    /// Sets the priority of the deal; lower values take precedence.
+    pub fn priority(mut self, priority: u32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Sets when the deal is on offer.
+    pub fn validity(mut self, validity: Validity) -> Self {
+        self.validity = validity;
+        self
+    }
+
+    /// Returns the deal, or the first problem found with its configuration.
+    pub fn build(self) -> Result<Deal, DealError> {
+        let deal = Deal {
+            product: self.product,
+            kind: self.kind.ok_or(DealError::MissingKind)?,
+            stacking: self.stacking,
+            priority: self.priority,
+            validity: self.validity,
+        };
+
+        deal.validate()?;
+        Ok(deal)
+    }
+}
+
+impl Display for Deal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.kind.fmt(f)
+    }
+}
+
+impl Display for DealKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(&CurrencyFormat::default()))
+    }
+}
+
+/// Picks the deals to apply to a single product by precedence, in the order they are applied.
+///
+/// The optimizer prefers this combination whenever it is as cheap as the alternatives.
+///
+/// Deals are ranked by `priority`, with ties going to the deal that was added first. If the
+/// top-ranked deal is exclusive it is the only one applied. Otherwise every stackable deal is
+/// applied in rank order and all exclusive deals are ignored.
+pub fn resolve<'d>(deals: impl IntoIterator<Item = &'d Deal>) -> Vec<&'d Deal> {
+    let mut deals: Vec<&Deal> = deals.into_iter().collect();
+    deals.sort_by_key(|deal| deal.priority);
+
+    match deals.first() {
+        Some(deal) if deal.stacking == Stacking::Exclusive => vec![deal],
+        _ => deals
+            .into_iter()
+            .filter(|deal| deal.stacking == Stacking::Stackable)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        deal::{resolve, Deal, DealError, DealKind, Percentage, Rounding, RoundingScope, Stacking},
+        money::{CurrencyCode, Money, RoundingMode},
+        test_support::eur,
+        validity::Validity,
+    };
+
+    fn bogof(stacking: Stacking, priority: u32) -> Deal {
+        Deal {
+            product: "A0002".to_string(),
+            kind: DealKind::Buy1Get1Free,
+            stacking,
+            priority,
+            validity: Validity::default(),
+        }
+    }
+
+    fn ten_percent(stacking: Stacking, priority: u32) -> Deal {
+        Deal {
+            product: "A0002".to_string(),
+            kind: DealKind::PercentageDiscount(Percentage::from_percent(10).unwrap()),
+            stacking,
+            priority,
+            validity: Validity::default(),
+        }
+    }
+
+    fn total(deals: &[Deal]) -> Money {
+        resolve(deals)
+            .into_iter()
+            .try_fold(Money::new(4 * 399, CurrencyCode::Eur), |amount, deal| {
+                deal.apply(amount, 4, Rounding::default())
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_stackable_deals_are_combined() {
+        let expected = Money::new(718, CurrencyCode::Eur);
+
+        assert_eq!(
+            expected,
+            total(&[
+                bogof(Stacking::Stackable, 0),
+                ten_percent(Stacking::Stackable, 1)
+            ])
+        );
+        assert_eq!(
+            expected,
+            total(&[
+                bogof(Stacking::Stackable, 1),
+                ten_percent(Stacking::Stackable, 0)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_exclusive_deal_with_top_priority_wins() {
+        assert_eq!(
+            Money::new(798, CurrencyCode::Eur),
+            total(&[
+                ten_percent(Stacking::Stackable, 1),
+                bogof(Stacking::Exclusive, 0)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_exclusive_deal_with_lower_priority_is_ignored() {
+        assert_eq!(
+            Money::new(1436, CurrencyCode::Eur),
+            total(&[
+                bogof(Stacking::Exclusive, 1),
+                ten_percent(Stacking::Stackable, 0)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_priority_tie_goes_to_first_added() {
+        assert_eq!(
+            Money::new(1436, CurrencyCode::Eur),
+            total(&[
+                ten_percent(Stacking::Exclusive, 0),
+                bogof(Stacking::Exclusive, 0)
+            ])
+        );
+        assert_eq!(
+            Money::new(798, CurrencyCode::Eur),
+            total(&[
+                bogof(Stacking::Exclusive, 0),
+                ten_percent(Stacking::Exclusive, 0)
+            ])
+        );
+    }
+
+    fn apply(kind: DealKind, amount: u64, quantity: u32) -> Money {
+        Deal {
+            product: "A0002".to_string(),
+            kind,
+            stacking: Stacking::Exclusive,
+            priority: 0,
+            validity: Validity::default(),
+        }
+        .apply(
+            Money::new(amount, CurrencyCode::Eur),
+            quantity,
+            Rounding::default(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_buy_n_get_m_free() {
+        let buy2get1 = || DealKind::BuyNGetMFree { buy: 2, free: 1 };
+
+        assert_eq!(eur(798), apply(buy2get1(), 798, 2));
+        assert_eq!(eur(798), apply(buy2get1(), 1197, 3));
+        assert_eq!(eur(1197), apply(buy2get1(), 1596, 4));
+        assert_eq!(eur(1596), apply(buy2get1(), 2394, 6));
+    }
+
+    #[test]
+    fn test_multi_buy_fixed_price() {
+        let three_for_ten = || DealKind::MultiBuyFixedPrice {
+            quantity: 3,
+            price: eur(1000),
+        };
+
+        assert_eq!(eur(798), apply(three_for_ten(), 798, 2));
+        assert_eq!(eur(1000), apply(three_for_ten(), 1197, 3));
+        assert_eq!(eur(1399), apply(three_for_ten(), 1596, 4));
+        assert_eq!(eur(900), apply(three_for_ten(), 900, 3));
+    }
+
+    #[test]
+    fn test_fixed_amount_off() {
+        let fifty_off = || DealKind::FixedAmountOff { amount: eur(50) };
+
+        assert_eq!(eur(349), apply(fifty_off(), 399, 1));
+        assert_eq!(eur(1047), apply(fifty_off(), 1197, 3));
+        assert_eq!(eur(0), apply(fifty_off(), 80, 2));
+    }
+
+    fn percentage_off(percentage: u32, amount: u64, quantity: u32, rounding: Rounding) -> u64 {
+        Deal {
+            product: "A0002".to_string(),
+            kind: DealKind::PercentageDiscount(Percentage::from_percent(percentage).unwrap()),
+            stacking: Stacking::Exclusive,
+            priority: 0,
+            validity: Validity::default(),
+        }
+        .apply(eur(amount), quantity, rounding)
+        .unwrap()
+        .amount
+    }
+
+    const MODES: [RoundingMode; 4] = [
+        RoundingMode::HalfUp,
+        RoundingMode::HalfEven,
+        RoundingMode::Floor,
+        RoundingMode::Ceil,
+    ];
+
+    #[test]
+    fn test_percentage_discount_per_line_rounding() {
+        let cases = [
+            // 3 x 3.99 at 50% off is 5.985.
+            (50, 1197, 3, [599, 598, 598, 599]),
+            // 3 x 3.97 at 50% off is 5.955.
+            (50, 1191, 3, [596, 596, 595, 596]),
+            // 3 x 3.99 at 15% off is 10.1745.
+            (15, 1197, 3, [1017, 1017, 1017, 1018]),
+        ];
+
+        for (percentage, amount, quantity, expected) in cases {
+            for (mode, expected) in MODES.into_iter().zip(expected) {
+                let rounding = Rounding {
+                    mode,
+                    scope: RoundingScope::PerLine,
+                };
+
+                assert_eq!(
+                    expected,
+                    percentage_off(percentage, amount, quantity, rounding),
+                    "{mode:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_percentage_discount_per_unit_rounding() {
+        let cases = [
+            // 3.99 at 50% off is 1.995 per unit.
+            (50, 1197, 3, [600, 600, 597, 600]),
+            // 3.97 at 50% off is 1.985 per unit.
+            (50, 1191, 3, [597, 594, 594, 597]),
+            // 3.99 at 15% off is 3.3915 per unit.
+            (15, 1197, 3, [1017, 1017, 1017, 1020]),
+        ];
+
+        for (percentage, amount, quantity, expected) in cases {
+            for (mode, expected) in MODES.into_iter().zip(expected) {
+                let rounding = Rounding {
+                    mode,
+                    scope: RoundingScope::PerUnit,
+                };
+
+                assert_eq!(
+                    expected,
+                    percentage_off(percentage, amount, quantity, rounding),
+                    "{mode:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_rounding() {
+        assert_eq!(
+            Ok(Rounding {
+                mode: RoundingMode::HalfEven,
+                scope: RoundingScope::PerUnit,
+            }),
+            "half-even-per-unit".parse()
+        );
+        assert_eq!(
+            Ok(Rounding {
+                mode: RoundingMode::Ceil,
+                scope: RoundingScope::PerLine,
+            }),
+            "ceil".parse()
+        );
+        assert!("nearest".parse::<Rounding>().is_err());
+    }
+
+    #[test]
+    fn test_percentage_in_basis_points() {
+        let percentage = Percentage::from_basis_points(1250).unwrap();
+
+        assert_eq!("12.5%", percentage.to_string());
+        assert_eq!(
+            "12.25%",
+            Percentage::from_basis_points(1225).unwrap().to_string()
+        );
+        assert_eq!("100%", Percentage::from_percent(100).unwrap().to_string());
+        // 3 x 3.99 at 12.5% off is 10.47375.
+        assert_eq!(
+            eur(1047),
+            percentage.take_off(eur(1197), RoundingMode::Floor).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_percentage_out_of_range() {
+        assert_eq!(
+            Err(DealError::PercentageOutOfRange(10_001)),
+            Percentage::from_basis_points(10_001)
+        );
+        assert_eq!(
+            Err(DealError::PercentageOutOfRange(12_000)),
+            Percentage::from_percent(120)
+        );
+    }
+
+    #[test]
+    fn test_builder() {
+        let deal = Deal::builder("A0002")
+            .kind(DealKind::BuyNGetMFree { buy: 2, free: 1 })
+            .stacking(Stacking::Stackable)
+            .priority(3)
+            .build()
+            .unwrap();
+
+        assert_eq!("A0002", deal.product);
+        assert_eq!(DealKind::BuyNGetMFree { buy: 2, free: 1 }, deal.kind);
+        assert_eq!(Stacking::Stackable, deal.stacking);
+        assert_eq!(3, deal.priority);
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_deals() {
+        let build = |product: &str, kind: Option<DealKind>| {
+            let builder = Deal::builder(product);
+
+            match kind {
+                Some(kind) => builder.kind(kind).build(),
+                None => builder.build(),
+            }
+            .unwrap_err()
+        };
+
+        assert_eq!(
+            DealError::EmptyProduct,
+            build("", Some(DealKind::Buy1Get1Free))
+        );
+        assert_eq!(DealError::MissingKind, build("A0002", None));
+        assert_eq!(
+            DealError::ZeroQuantity("free"),
+            build("A0002", Some(DealKind::BuyNGetMFree { buy: 2, free: 0 }))
+        );
+        assert_eq!(
+            DealError::ZeroQuantity("quantity"),
+            build(
+                "A0002",
+                Some(DealKind::MultiBuyFixedPrice {
+                    quantity: 0,
+                    price: eur(700),
+                })
+            )
+        );
+        assert_eq!(
+            "deal parameter 'free' must be at least 1",
+            DealError::ZeroQuantity("free").to_string()
+        );
+        assert_eq!(
+            Err(DealError::EmptyValidity),
+            Deal::builder("A0002")
+                .kind(DealKind::Buy1Get1Free)
+                .validity(Validity {
+                    start: Some("2024-03-11T00:00:00".parse().unwrap()),
+                    end: Some("2024-03-04T00:00:00".parse().unwrap()),
+                    recurrence: None,
+                })
+                .build()
+        );
+    }
+}
@@ -0,0 +1,219 @@
+//! The log of changes made to a basket.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{deal::Deal, group::GroupDeal, rule::Rule, threshold::ThresholdDeal};
+
+/// An action performed on a basket, as recorded in its event log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    /// Numbers events from 1, in the order they happened.
+    pub seq: u64,
+    /// When the action was performed by the basket's clock, in milliseconds since the Unix
+    /// epoch.
+    pub timestamp: u64,
+    pub action: Action,
+}
+
+/// A change to a basket. Deals are recorded in full, so that a log can be replayed on its own.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Action {
+    Scan {
+        sku: String,
+        quantity: u32,
+    },
+    ScanWeighted {
+        sku: String,
+        grams: u32,
+    },
+    Remove {
+        sku: String,
+    },
+    SetQuantity {
+        sku: String,
+        quantity: u32,
+    },
+    VoidLine {
+        sku: String,
+    },
+    AddDeal {
+        deal: Deal,
+    },
+    AddGroupDeal {
+        deal: GroupDeal,
+    },
+    AddThresholdDeal {
+        deal: ThresholdDeal,
+    },
+    AddRule {
+        rule: Rule,
+    },
+    /// Reverts the most recent change that has not been undone yet.
+    Undo,
+    /// Reapplies the most recently undone change.
+    Redo,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        basket::{Basket, BasketError},
+        clock::FixedClock,
+        deal::DealError,
+        event::{Action, Event},
+        test_support::{buy1get1free, catalog, eur},
+    };
+
+    fn actions(basket: &Basket) -> Vec<Action> {
+        basket
+            .events()
+            .iter()
+            .map(|event| event.action.clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_actions_are_logged_in_order() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        basket.set_quantity("A0002", 3).unwrap();
+        basket.remove("A0002").unwrap();
+        // Failed actions are not logged.
+        basket.scan("A0009").unwrap_err();
+
+        assert_eq!(
+            vec![
+                Action::Scan {
+                    sku: "A0002".to_string(),
+                    quantity: 1,
+                },
+                Action::AddDeal {
+                    deal: buy1get1free(),
+                },
+                Action::SetQuantity {
+                    sku: "A0002".to_string(),
+                    quantity: 3,
+                },
+                Action::Remove {
+                    sku: "A0002".to_string(),
+                },
+            ],
+            actions(&basket)
+        );
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            basket
+                .events()
+                .iter()
+                .map(|event| event.seq)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_events_are_timestamped_by_the_basket_clock() {
+        let mut basket = Basket::new(catalog());
+
+        basket.set_clock(FixedClock(
+            "2024-03-08".parse::<chrono::NaiveDate>().unwrap().into(),
+        ));
+        basket.scan("A0002").unwrap();
+        basket.undo().unwrap();
+
+        assert_eq!(
+            vec![1_709_856_000_000, 1_709_856_000_000],
+            basket
+                .events()
+                .iter()
+                .map(|event| event.timestamp)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        assert_eq!(eur(1698), basket.total().unwrap());
+
+        basket.undo().unwrap();
+        assert_eq!(eur(399), basket.total().unwrap());
+
+        basket.undo().unwrap();
+        basket.undo().unwrap();
+        assert_eq!(eur(399), basket.total().unwrap());
+        basket.scan("A0002").unwrap();
+        assert_eq!(eur(798), basket.total().unwrap());
+
+        // The deal was undone, and scanning again discarded it for good.
+        assert_eq!(Err(BasketError::NothingToRedo), basket.redo());
+        basket.undo().unwrap();
+        basket.redo().unwrap();
+        assert_eq!(eur(798), basket.total().unwrap());
+
+        assert_eq!(Some(&Action::Redo), actions(&basket).last());
+    }
+
+    #[test]
+    fn test_nothing_to_undo() {
+        let mut basket = Basket::new(catalog());
+
+        assert_eq!(Err(BasketError::NothingToUndo), basket.undo());
+        assert!(basket.events().is_empty());
+    }
+
+    #[test]
+    fn test_replay_serialized_log() {
+        let catalog = catalog();
+        let mut basket = Basket::new(Arc::clone(&catalog));
+
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        basket.scan("A0002").unwrap();
+        basket.void_line("A0001").unwrap();
+        basket.undo().unwrap();
+
+        let log = serde_json::to_string(basket.events()).unwrap();
+        let events: Vec<Event> = serde_json::from_str(&log).unwrap();
+        let replayed = Basket::replay(Arc::clone(&catalog), &events).unwrap();
+
+        assert_eq!(basket.events(), replayed.events());
+        assert_eq!(basket.receipt(), replayed.receipt());
+        assert_eq!(eur(1698), replayed.total().unwrap());
+    }
+
+    #[test]
+    fn test_replay_rejects_invalid_deal() {
+        let events: Vec<Event> = serde_json::from_str(
+            r#"[{
+                "seq": 1,
+                "timestamp": 0,
+                "action": {
+                    "type": "add_deal",
+                    "deal": {
+                        "product": "A0002",
+                        "kind": { "buy_n_get_m_free": { "buy": 0, "free": 0 } },
+                        "stacking": "exclusive",
+                        "priority": 0
+                    }
+                }
+            }]"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            BasketError::InvalidDeal(DealError::ZeroQuantity("buy")),
+            Basket::replay(catalog(), &events).unwrap_err()
+        );
+    }
+}
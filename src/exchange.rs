@@ -0,0 +1,278 @@
+//! Exchange rates for paying in a currency other than the catalog's.
+
+use std::{collections::HashMap, fmt::Display, fs, path::Path};
+
+use crate::money::{CurrencyCode, Money, MoneyError, RoundingMode};
+
+/// The most decimal places accepted in a rate, which keeps conversions within `u128`.
+const MAX_SCALE: u32 = 12;
+
+/// A fixed-point conversion rate: one unit of `from` buys `rate` units of `to`.
+///
+/// The rate is kept exactly as written in the source file (`mantissa / 10^scale`) so that
+/// no precision is lost before it is applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExchangeRate {
+    pub from: CurrencyCode,
+    pub to: CurrencyCode,
+    mantissa: u64,
+    scale: u32,
+}
+
+/// Known exchange rates, keyed by currency pair.
+#[derive(Debug, Default)]
+pub struct ExchangeRates {
+    rates: HashMap<(CurrencyCode, CurrencyCode), ExchangeRate>,
+}
+
+/// Why exchange rates could not be loaded.
+#[derive(Debug)]
+pub enum ExchangeRateError {
+    Io(std::io::Error),
+    Invalid { line: usize, reason: String },
+}
+
+impl Display for ExchangeRateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExchangeRateError::Io(error) => write!(f, "could not read exchange rates: {error}"),
+            ExchangeRateError::Invalid { line, reason } => write!(f, "line {line}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ExchangeRateError {}
+
+impl From<std::io::Error> for ExchangeRateError {
+    fn from(error: std::io::Error) -> Self {
+        ExchangeRateError::Io(error)
+    }
+}
+
+impl ExchangeRate {
+    /// Parses a decimal rate such as `1.0843`.
+    pub fn new(from: CurrencyCode, to: CurrencyCode, rate: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid rate '{rate}'");
+
+        let (units, fraction) = rate.split_once('.').unwrap_or((rate, ""));
+        if units.is_empty()
+            || !units
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+
+        let scale = fraction.len() as u32;
+        if scale > MAX_SCALE {
+            return Err(format!(
+                "rate '{rate}' has more than {MAX_SCALE} decimal places"
+            ));
+        }
+
+        let mantissa: u64 = format!("{units}{fraction}")
+            .parse()
+            .map_err(|_| invalid())?;
+        if mantissa == 0 {
+            return Err(format!("rate '{rate}' must be greater than zero"));
+        }
+
+        Ok(Self {
+            from,
+            to,
+            mantissa,
+            scale,
+        })
+    }
+
+    fn identity(currency: CurrencyCode) -> Self {
+        Self {
+            from: currency,
+            to: currency,
+            mantissa: 1,
+            scale: 0,
+        }
+    }
+
+    /// Converts `money` into the target currency.
+    ///
+    /// The exact product is rounded half up to the nearest minor unit of the target currency,
+    /// taking the minor-unit exponents of both currencies into account.
+    pub fn convert(&self, money: Money) -> Result<Money, MoneyError> {
+        if money.currency != self.from {
+            return Err(MoneyError::CurrencyMismatch {
+                expected: self.from,
+                found: money.currency,
+            });
+        }
+
+        let numerator = u128::from(money.amount)
+            .checked_mul(u128::from(self.mantissa))
+            .and_then(|numerator| numerator.checked_mul(10u128.pow(self.to.exponent())));
+        let denominator = 10u128.pow(self.scale + self.from.exponent());
+
+        numerator
+            .and_then(|numerator| RoundingMode::HalfUp.div(numerator, denominator))
+            .and_then(|amount| amount.try_into().ok())
+            .map(|amount| Money::new(amount, self.to))
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+impl Display for ExchangeRate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let divisor = 10u64.pow(self.scale);
+
+        write!(f, "1 {} = {}", self.from, self.mantissa / divisor)?;
+        if self.scale > 0 {
+            write!(
+                f,
+                ".{:0width$}",
+                self.mantissa % divisor,
+                width = self.scale as usize
+            )?;
+        }
+        write!(f, " {}", self.to)
+    }
+}
+
+impl ExchangeRates {
+    /// Loads rates from a CSV file, see [`ExchangeRates::from_csv`].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ExchangeRateError> {
+        Self::from_csv(&fs::read_to_string(path)?)
+    }
+
+    /// Parses `from,to,rate` rows such as `EUR,USD,1.0843`.
+    ///
+    /// The header row is required. Blank lines and lines starting with `#` are skipped.
+    pub fn from_csv(input: &str) -> Result<Self, ExchangeRateError> {
+        let mut lines = input
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, "from,to,rate")) => {}
+            Some((line, header)) => {
+                return Err(ExchangeRateError::Invalid {
+                    line,
+                    reason: format!("expected header 'from,to,rate', found '{header}'"),
+                })
+            }
+            None => return Ok(Self::default()),
+        }
+
+        let mut rates = Self::default();
+
+        for (line, row) in lines {
+            let invalid = |reason| ExchangeRateError::Invalid { line, reason };
+            let fields: Vec<&str> = row.split(',').map(str::trim).collect();
+
+            let [from, to, rate] = fields[..] else {
+                return Err(invalid(format!(
+                    "expected 3 fields, found {}",
+                    fields.len()
+                )));
+            };
+
+            let rate = ExchangeRate::new(
+                from.parse().map_err(invalid)?,
+                to.parse().map_err(invalid)?,
+                rate,
+            )
+            .map_err(invalid)?;
+
+            if rate.from == rate.to {
+                return Err(invalid(format!("rate converts {} to itself", rate.from)));
+            }
+
+            if rates.rates.insert((rate.from, rate.to), rate).is_some() {
+                return Err(invalid(format!(
+                    "duplicate rate for {} to {}",
+                    rate.from, rate.to
+                )));
+            }
+        }
+
+        Ok(rates)
+    }
+
+    /// Looks up the rate from one currency to another.
+    ///
+    /// Only rates listed explicitly are used; the inverse of a listed rate is not derived.
+    /// Converting a currency to itself always uses a rate of 1.
+    pub fn get(&self, from: CurrencyCode, to: CurrencyCode) -> Option<ExchangeRate> {
+        if from == to {
+            return Some(ExchangeRate::identity(from));
+        }
+
+        self.rates.get(&(from, to)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exchange::{ExchangeRate, ExchangeRateError, ExchangeRates},
+        money::{CurrencyCode, Money, MoneyError},
+    };
+
+    #[test]
+    fn test_convert_rounds_half_up() {
+        let rate = ExchangeRate::new(CurrencyCode::Eur, CurrencyCode::Usd, "1.5").unwrap();
+
+        assert_eq!(
+            Ok(Money::new(2, CurrencyCode::Usd)),
+            rate.convert(Money::new(1, CurrencyCode::Eur))
+        );
+        assert_eq!(
+            Ok(Money::new(1950, CurrencyCode::Usd)),
+            rate.convert(Money::new(1300, CurrencyCode::Eur))
+        );
+        assert_eq!(
+            Err(MoneyError::Overflow),
+            rate.convert(Money::new(u64::MAX, CurrencyCode::Eur))
+        );
+    }
+
+    #[test]
+    fn test_convert_between_exponents() {
+        let jpy = ExchangeRate::new(CurrencyCode::Eur, CurrencyCode::Jpy, "162.35").unwrap();
+        let bhd = ExchangeRate::new(CurrencyCode::Eur, CurrencyCode::Bhd, "0.408812").unwrap();
+
+        assert_eq!(
+            Ok(Money::new(2757, CurrencyCode::Jpy)),
+            jpy.convert(Money::new(1698, CurrencyCode::Eur))
+        );
+        assert_eq!(
+            Ok(Money::new(6942, CurrencyCode::Bhd)),
+            bhd.convert(Money::new(1698, CurrencyCode::Eur))
+        );
+    }
+
+    #[test]
+    fn test_rate_display() {
+        let rate = ExchangeRate::new(CurrencyCode::Eur, CurrencyCode::Usd, "1.0843").unwrap();
+
+        assert_eq!("1 EUR = 1.0843 USD", rate.to_string());
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let rates = ExchangeRates::from_csv("from,to,rate\nEUR,USD,1.0843\n").unwrap();
+
+        assert!(rates.get(CurrencyCode::Eur, CurrencyCode::Usd).is_some());
+        assert!(rates.get(CurrencyCode::Usd, CurrencyCode::Eur).is_none());
+        assert!(rates.get(CurrencyCode::Gbp, CurrencyCode::Gbp).is_some());
+    }
+
+    #[test]
+    fn test_from_csv_reports_line() {
+        let error =
+            ExchangeRates::from_csv("from,to,rate\nEUR,USD,1.0843\nEUR,GBP,-1\n").unwrap_err();
+
+        assert!(matches!(error, ExchangeRateError::Invalid { line: 3, .. }));
+    }
+}
@@ -0,0 +1,441 @@
+//! Deals across a group of products.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    basket::BasketError,
+    catalog::Product,
+    catalog::ProductGroup,
+    deal::DealError,
+    money::{CurrencyCode, CurrencyFormat, Money},
+};
+
+/// A deal whose qualifying and rewarded items are sets of SKUs rather than a single product.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupDeal {
+    pub kind: GroupDealKind,
+    /// Lower values are applied first.
+    pub priority: u32,
+}
+
+/// How a [`GroupDeal`] picks the units it applies to and what it takes off.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GroupDealKind {
+    /// Any `quantity` units from the group cost `price` together.
+    ///
+    /// Units are grouped starting with the most expensive ones, and a group is only formed if
+    /// it is cheaper than paying for its units separately.
+    MixAndMatch {
+        group: ProductGroup,
+        quantity: u32,
+        price: Money,
+    },
+    /// For every `quantity` units bought from the group, one is free.
+    ///
+    /// The free units are the cheapest units in the group across the whole basket, e.g. when
+    /// buying six items on "3 for 2" the two cheapest are free.
+    CheapestFree { group: ProductGroup, quantity: u32 },
+    /// Every unit bought from `buy` makes one unit from `get` free, cheapest first.
+    ///
+    /// A SKU that is in both groups only counts towards `buy`.
+    BuyGetFree {
+        buy: ProductGroup,
+        get: ProductGroup,
+    },
+}
+
+/// Units claimed by a group deal: what they would cost and what the customer pays instead.
+#[derive(Debug, PartialEq)]
+pub struct Claim {
+    pub regular: Money,
+    pub charged: Money,
+}
+
+/// The units of a product that are still available to deals.
+pub type Line<'p> = (&'p Product, u32);
+
+impl GroupDeal {
+    /// Returns every SKU the deal refers to.
+    pub fn skus(&self) -> impl Iterator<Item = &String> {
+        let (first, second) = match &self.kind {
+            GroupDealKind::MixAndMatch { group, .. }
+            | GroupDealKind::CheapestFree { group, .. } => (group, None),
+            GroupDealKind::BuyGetFree { buy, get } => (buy, Some(get)),
+        };
+
+        first
+            .skus
+            .iter()
+            .chain(second.into_iter().flat_map(|group| &group.skus))
+    }
+
+    /// The amount of money the deal is defined in, if it has one.
+    pub fn amount(&self) -> Option<Money> {
+        match self.kind {
+            GroupDealKind::MixAndMatch { price, .. } => Some(price),
+            GroupDealKind::CheapestFree { .. } | GroupDealKind::BuyGetFree { .. } => None,
+        }
+    }
+
+    /// Returns the first problem found with the configuration of the deal.
+    pub fn validate(&self) -> Result<(), DealError> {
+        match self.kind {
+            GroupDealKind::MixAndMatch { quantity: 0, .. }
+            | GroupDealKind::CheapestFree { quantity: 0, .. } => {
+                Err(DealError::ZeroQuantity("quantity"))
+            }
+            GroupDealKind::CheapestFree { quantity: 1, .. } => Err(DealError::EveryUnitFree),
+            _ => Ok(()),
+        }
+    }
+
+    /// Describes the deal as it is shown on a receipt, formatting amounts according to
+    /// `format`.
+    pub fn describe(&self, format: &CurrencyFormat) -> String {
+        match &self.kind {
+            GroupDealKind::MixAndMatch {
+                group,
+                quantity,
+                price,
+            } => format!(
+                "Any {quantity} {} for {}",
+                group.name,
+                price.display_with(format)
+            ),
+            GroupDealKind::CheapestFree { group, quantity } => {
+                format!(
+                    "{quantity} for {} on {}",
+                    quantity.saturating_sub(1),
+                    group.name
+                )
+            }
+            GroupDealKind::BuyGetFree { buy, get } => {
+                format!("Buy {} get {} free", buy.name, get.name)
+            }
+        }
+    }
+
+    /// Claims the units the deal applies to from `lines`. Claimed units are removed from
+    /// `lines`, so they cannot be used by other deals.
+    pub fn apply(&self, lines: &mut [Line], currency: CurrencyCode) -> Result<Claim, BasketError> {
+        let mut regular = Money::zero(currency);
+        let mut charged = Money::zero(currency);
+
+        match &self.kind {
+            GroupDealKind::MixAndMatch {
+                group,
+                quantity,
+                price,
+            } => {
+                let order = eligible(lines, |sku| group.contains(sku));
+
+                while *quantity > 0 && available(lines, &order) >= u64::from(*quantity) {
+                    let group = take(&mut lines.to_vec(), &order, *quantity, currency)?;
+                    if group.amount <= price.amount {
+                        break;
+                    }
+
+                    take(lines, &order, *quantity, currency)?;
+                    regular = regular.checked_add(group)?;
+                    charged = charged.checked_add(*price)?;
+                }
+            }
+            GroupDealKind::CheapestFree { group, quantity } => {
+                let order = eligible(lines, |sku| group.contains(sku));
+                let free = available(lines, &order)
+                    .checked_div(u64::from(*quantity))
+                    .unwrap_or(0);
+                let paid = free * u64::from(quantity.saturating_sub(1));
+
+                charged = take(lines, &order, units(paid)?, currency)?;
+                let cheapest_first: Vec<usize> = order.into_iter().rev().collect();
+                regular =
+                    take(lines, &cheapest_first, units(free)?, currency)?.checked_add(charged)?;
+            }
+            GroupDealKind::BuyGetFree { buy, get } => {
+                let qualifying = eligible(lines, |sku| buy.contains(sku));
+                let rewarded: Vec<usize> =
+                    eligible(lines, |sku| get.contains(sku) && !buy.contains(sku))
+                        .into_iter()
+                        .rev()
+                        .collect();
+                let free = available(lines, &qualifying).min(available(lines, &rewarded));
+
+                charged = take(lines, &qualifying, units(free)?, currency)?;
+                regular = take(lines, &rewarded, units(free)?, currency)?.checked_add(charged)?;
+            }
+        }
+
+        Ok(Claim { regular, charged })
+    }
+}
+
+impl Display for GroupDeal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(&CurrencyFormat::default()))
+    }
+}
+
+/// Indices of the lines matching `filter` that still have units, most expensive first.
+///
+/// Products sold by weight or volume have no units to count, so they are never eligible.
+fn eligible(lines: &[Line], filter: impl Fn(&str) -> bool) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..lines.len())
+        .filter(|&index| {
+            let (product, available) = lines[index];
+
+            available > 0 && !product.unit.is_measured() && filter(&product.sku)
+        })
+        .collect();
+
+    order.sort_by(|&a, &b| {
+        let (a, b) = (lines[a].0, lines[b].0);
+        b.price.amount.cmp(&a.price.amount).then(a.sku.cmp(&b.sku))
+    });
+
+    order
+}
+
+fn available(lines: &[Line], order: &[usize]) -> u64 {
+    order.iter().map(|&index| u64::from(lines[index].1)).sum()
+}
+
+fn units(count: u64) -> Result<u32, BasketError> {
+    count.try_into().map_err(|_| BasketError::Overflow)
+}
+
+/// Removes `count` units from the lines in `order` and returns their regular price.
+fn take(
+    lines: &mut [Line],
+    order: &[usize],
+    mut count: u32,
+    currency: CurrencyCode,
+) -> Result<Money, BasketError> {
+    let mut regular = Money::zero(currency);
+
+    for &index in order {
+        let (product, available) = &mut lines[index];
+        let taken = count.min(*available);
+
+        *available -= taken;
+        count -= taken;
+        regular = regular.checked_add(product.price.checked_mul(taken)?)?;
+    }
+
+    Ok(regular)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        basket::{Basket, BasketError},
+        catalog::{Catalog, ProductGroup},
+        deal::{Deal, DealError, DealKind, Percentage, Stacking},
+        group::{GroupDeal, GroupDealKind},
+        money::{CurrencyCode, Money},
+        test_support::eur,
+        validity::Validity,
+    };
+
+    fn catalog() -> Arc<Catalog> {
+        Arc::new(
+            Catalog::from_csv(
+                "sku,name,price,currency,category\n\
+                 S1,Shampoo,500,EUR,haircare\n\
+                 S2,Shampoo Deluxe,800,EUR,haircare\n\
+                 C1,Conditioner,300,EUR,haircare\n\
+                 C2,Conditioner Deluxe,600,EUR,haircare\n\
+                 A0001,Water,1299,EUR,\n",
+            )
+            .unwrap(),
+        )
+    }
+
+    fn total(catalog: &Arc<Catalog>, skus: &[&str], deals: &[GroupDeal]) -> Money {
+        let mut basket = Basket::new(Arc::clone(catalog));
+
+        for sku in skus {
+            basket.scan(sku).unwrap();
+        }
+        for deal in deals {
+            basket.add_group_deal(deal.clone()).unwrap();
+        }
+
+        basket.total().unwrap()
+    }
+
+    #[test]
+    fn test_mix_and_match() {
+        let catalog = catalog();
+        let deal = GroupDeal {
+            kind: GroupDealKind::MixAndMatch {
+                group: catalog.category("haircare"),
+                quantity: 3,
+                price: eur(1000),
+            },
+            priority: 0,
+        };
+
+        // S2, C2 and S1 form the group; C1 is charged on its own.
+        assert_eq!(
+            eur(1300),
+            total(&catalog, &["S1", "S2", "C1", "C2"], &[deal])
+        );
+    }
+
+    #[test]
+    fn test_mix_and_match_only_when_cheaper() {
+        let catalog = catalog();
+        let deal = GroupDeal {
+            kind: GroupDealKind::MixAndMatch {
+                group: catalog.category("haircare"),
+                quantity: 2,
+                price: eur(1000),
+            },
+            priority: 0,
+        };
+
+        // S2 + C2 for 10.00 instead of 14.00, but S1 + C1 would cost more as a pair.
+        assert_eq!(
+            eur(1800),
+            total(&catalog, &["S1", "S2", "C1", "C2"], &[deal])
+        );
+    }
+
+    #[test]
+    fn test_cheapest_free() {
+        let catalog = catalog();
+        let deal = || GroupDeal {
+            kind: GroupDealKind::CheapestFree {
+                group: catalog.category("haircare"),
+                quantity: 3,
+            },
+            priority: 0,
+        };
+
+        assert_eq!(eur(1300), total(&catalog, &["S1", "S2", "C1"], &[deal()]));
+        // Six units: the two cheapest (both C1) are free.
+        assert_eq!(
+            eur(2700),
+            total(&catalog, &["S1", "S2", "C1", "C2", "C1", "S2"], &[deal()])
+        );
+    }
+
+    #[test]
+    fn test_invalid_group_deals() {
+        let catalog = catalog();
+        let add = |kind| {
+            Basket::new(Arc::clone(&catalog)).add_group_deal(GroupDeal { kind, priority: 0 })
+        };
+
+        assert_eq!(
+            Err(BasketError::InvalidDeal(DealError::EveryUnitFree)),
+            add(GroupDealKind::CheapestFree {
+                group: catalog.category("haircare"),
+                quantity: 1,
+            })
+        );
+        assert_eq!(
+            Err(BasketError::InvalidDeal(DealError::ZeroQuantity(
+                "quantity"
+            ))),
+            add(GroupDealKind::CheapestFree {
+                group: catalog.category("haircare"),
+                quantity: 0,
+            })
+        );
+        assert_eq!(
+            Err(BasketError::InvalidDeal(DealError::ZeroQuantity(
+                "quantity"
+            ))),
+            add(GroupDealKind::MixAndMatch {
+                group: catalog.category("haircare"),
+                quantity: 0,
+                price: eur(1000),
+            })
+        );
+        assert_eq!(
+            Err(BasketError::CurrencyMismatch {
+                expected: CurrencyCode::Eur,
+                found: CurrencyCode::Jpy,
+            }),
+            add(GroupDealKind::MixAndMatch {
+                group: catalog.category("haircare"),
+                quantity: 2,
+                price: Money::new(1000, CurrencyCode::Jpy),
+            })
+        );
+    }
+
+    #[test]
+    fn test_buy_get_free() {
+        let catalog = catalog();
+        let deal = GroupDeal {
+            kind: GroupDealKind::BuyGetFree {
+                buy: ProductGroup::new("shampoo", ["S1", "S2"]),
+                get: ProductGroup::new("conditioner", ["C1", "C2"]),
+            },
+            priority: 0,
+        };
+
+        // One shampoo, so only the cheaper conditioner is free.
+        assert_eq!(eur(1100), total(&catalog, &["S1", "C1", "C2"], &[deal]));
+    }
+
+    #[test]
+    fn test_claimed_units_skip_product_deals() {
+        let catalog = catalog();
+        let group_deal = GroupDeal {
+            kind: GroupDealKind::BuyGetFree {
+                buy: ProductGroup::new("water", ["A0001"]),
+                get: ProductGroup::new("conditioner", ["C1"]),
+            },
+            priority: 0,
+        };
+        let deal = Deal {
+            product: "C1".to_string(),
+            kind: DealKind::PercentageDiscount(Percentage::from_percent(50).unwrap()),
+            stacking: Stacking::Exclusive,
+            priority: 0,
+            validity: Validity::default(),
+        };
+
+        let mut basket = Basket::new(Arc::clone(&catalog));
+        basket.scan("A0001").unwrap();
+        basket.scan_quantity("C1", 2).unwrap();
+        basket.add_group_deal(group_deal).unwrap();
+        basket.add_deal(deal).unwrap();
+
+        // One C1 is free, the other is half price.
+        assert_eq!(eur(1449), basket.total().unwrap());
+    }
+
+    #[test]
+    fn test_weighted_lines_are_not_eligible() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category,unit\n\
+             A1,Apples,299,EUR,fruit,kg\n\
+             P1,Pineapple,250,EUR,fruit,piece\n",
+        )
+        .unwrap();
+        let deal = GroupDeal {
+            kind: GroupDealKind::CheapestFree {
+                group: catalog.category("fruit"),
+                quantity: 2,
+            },
+            priority: 0,
+        };
+
+        let mut basket = Basket::new(Arc::new(catalog));
+        basket.scan_weighted("A1", 1000).unwrap();
+        basket.scan("P1").unwrap();
+        basket.add_group_deal(deal).unwrap();
+
+        assert_eq!(eur(549), basket.total().unwrap());
+    }
+}
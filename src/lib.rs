@@ -0,0 +1,47 @@
+//! Prices baskets of products against a catalog and the deals on offer.
+//!
+//! A [`Catalog`] lists the products for sale. A [`Basket`] shares a catalog, keeps track of
+//! what was scanned and which deals apply, and prices everything into a [`Receipt`], always
+//! giving the customer the cheapest combination of deals.
+//!
+//! ```
+//! use std::sync::Arc;
+//!
+//! use bitside_coding_challenge::{deal::DealKind, money::CurrencyCode, Basket, Catalog, Deal, Money};
+//!
+//! let catalog = Catalog::from_csv(
+//!     "sku,name,price,currency\nA0001,Water,1299,EUR\nA0002,Soap,399,EUR\n",
+//! )?;
+//! let mut basket = Basket::new(Arc::new(catalog));
+//!
+//! basket.scan("A0001")?;
+//! basket.scan_quantity("A0002", 2)?;
+//! basket.add_deal(Deal::builder("A0002").kind(DealKind::Buy1Get1Free).build()?)?;
+//!
+//! assert_eq!(Money::new(1698, CurrencyCode::Eur), basket.total()?);
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod basket;
+pub mod catalog;
+pub mod clock;
+pub mod deal;
+pub mod event;
+pub mod exchange;
+pub mod group;
+pub mod money;
+mod optimizer;
+pub mod promotion;
+pub mod receipt;
+pub mod rule;
+pub mod tax;
+#[cfg(test)]
+pub(crate) mod test_support;
+pub mod threshold;
+pub mod validity;
+
+pub use basket::{Basket, BasketError};
+pub use catalog::{Catalog, Product};
+pub use deal::Deal;
+pub use money::{CurrencyCode, Money};
+pub use receipt::Receipt;
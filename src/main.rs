@@ -1,196 +1,394 @@
-use std::{collections::HashMap, fmt::Display, iter::Sum};
+use std::{error::Error, sync::Arc};
+
+use bitside_coding_challenge::{
+    basket::{Basket, BasketError, LineOrder},
+    catalog::{Catalog, ProductGroup},
+    clock::FixedClock,
+    deal::{Deal, DealKind, Percentage, Rounding, Stacking},
+    event::Event,
+    exchange::ExchangeRates,
+    group::{GroupDeal, GroupDealKind},
+    money::{CurrencyCode, CurrencyFormat, Money},
+    promotion::Promotion,
+    tax::TaxJurisdiction,
+    threshold::{ThresholdBase, ThresholdDeal, ThresholdReward},
+};
+use chrono::{NaiveDate, NaiveDateTime};
 
-use lazy_static::lazy_static;
-
-#[derive(Debug)]
-struct Basket<'a> {
-    products: HashMap<&'a Product, u32>,
-    deals: Vec<&'a Deal>,
-}
-
-#[derive(Debug, Hash, Eq, PartialEq)]
-struct Product {
-    name: String,
-    price: Currency,
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("error: {error}");
+        std::process::exit(1);
+    }
 }
 
-#[derive(Debug, Hash, Eq, PartialEq)]
-struct Currency(u32);
-
-#[derive(Debug)]
-struct Deal {
-    product: String,
-    kind: DealKind,
+/// How to run the demo, e.g. `catalog.csv --locale de-DE --at 2020-08-01`.
+const USAGE: &str = "usage: bitside-coding-challenge [catalog] [--locale <locale>] \
+[--pay-in <currency>] [--rounding <rounding>] [--order <order>] \
+[--jurisdiction <file>] [--at <date or datetime>]";
+
+/// The command line of the demo: an optional catalog path and named options.
+#[derive(Debug, PartialEq)]
+struct Options {
+    catalog: String,
+    format: CurrencyFormat,
+    /// The currency the customer pays in, if not the catalog's.
+    payment: Option<CurrencyCode>,
+    rounding: Rounding,
+    order: LineOrder,
+    jurisdiction: Option<String>,
+    clock: Option<FixedClock>,
 }
 
-#[derive(Debug)]
-enum DealKind {
-    Buy1Get1Free,
-    PercentageDiscount(u32),
-}
+impl Options {
+    /// Parses the arguments after the program name. Options take a value either as the next
+    /// argument or after `=`, as in `--at=2020-08-01`.
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options {
+            catalog: "catalog.csv".to_string(),
+            format: CurrencyFormat::default(),
+            payment: None,
+            rounding: Rounding::default(),
+            order: LineOrder::default(),
+            jurisdiction: None,
+            clock: None,
+        };
+        let mut catalog = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            let Some(option) = arg.strip_prefix("--") else {
+                if catalog.replace(arg.clone()).is_some() {
+                    return Err(format!("unexpected argument '{arg}'"));
+                }
+                continue;
+            };
+            let (name, value) = match option.split_once('=') {
+                Some((name, value)) => (name, value.to_string()),
+                None => (
+                    option,
+                    args.next()
+                        .ok_or_else(|| format!("option '--{option}' needs a value"))?,
+                ),
+            };
+
+            match name {
+                "locale" => {
+                    options.format = CurrencyFormat::for_locale(&value)
+                        .ok_or_else(|| format!("unsupported locale '{value}'"))?
+                }
+                "pay-in" => options.payment = Some(value.parse()?),
+                "rounding" => options.rounding = value.parse()?,
+                "order" => options.order = value.parse()?,
+                "jurisdiction" => options.jurisdiction = Some(value),
+                "at" => {
+                    let at = value
+                        .parse::<NaiveDateTime>()
+                        .or_else(|_| value.parse::<NaiveDate>().map(NaiveDateTime::from))
+                        .map_err(|error| format!("invalid date '{value}': {error}"))?;
+                    options.clock = Some(FixedClock(at));
+                }
+                _ => return Err(format!("unknown option '--{name}'")),
+            }
+        }
 
-impl Display for Currency {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.write_str(&format!("{}", self.0 / 100))?;
-        f.write_str(".")?;
-        f.write_str(&format!("{}", self.0 % 100))
-    }
-}
+        if let Some(catalog) = catalog {
+            options.catalog = catalog;
+        }
 
-impl Sum for Currency {
-    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
-        Currency(iter.map(|p| p.0).sum())
+        Ok(options)
     }
 }
 
-impl Product {
-    pub fn new(name: String, price: u32) -> Self {
-        Self {
-            name,
-            price: Currency(price),
+fn run() -> Result<(), Box<dyn Error>> {
+    let Options {
+        catalog: path,
+        format,
+        payment,
+        rounding,
+        order,
+        jurisdiction,
+        clock,
+    } = Options::parse(std::env::args().skip(1)).map_err(|error| format!("{error}\n{USAGE}"))?;
+
+    let catalog = Arc::new(Catalog::from_path(&path).map_err(|error| format!("{path}: {error}"))?);
+
+    let payment = match payment {
+        Some(currency) => {
+            let rates = ExchangeRates::from_path("exchange_rates.csv")
+                .map_err(|error| format!("exchange_rates.csv: {error}"))?;
+
+            Some((currency, rates))
         }
-    }
-}
+        None => None,
+    };
 
-impl<'a> Basket<'a> {
-    pub fn new() -> Self {
-        Basket {
-            products: HashMap::new(),
-            deals: Vec::new(),
-        }
-    }
+    let tax = match jurisdiction {
+        Some(path) => Some(Arc::new(
+            TaxJurisdiction::from_path(&path).map_err(|error| format!("{path}: {error}"))?,
+        )),
+        None => None,
+    };
 
-    pub fn scan(&mut self, product_name: &str) -> Result<(), ()> {
-        let product = INVENTORY.get(product_name).ok_or(())?;
+    let promotions = Promotion::from_path("promotions.json", &catalog)
+        .map_err(|error| format!("promotions.json: {error}"))?;
 
-        self.products
-            .entry(product)
-            .and_modify(|quantity| *quantity += 1)
-            .or_insert(1);
+    for promotion in &promotions {
+        let mut basket = demo_basket(&catalog, rounding, order, tax.as_ref(), clock)?;
 
-        Ok(())
-    }
+        add_promotion(&mut basket, promotion)?;
 
-    pub fn add_deal(&mut self, deal: &'a Deal) {
-        self.deals.push(deal);
+        print_receipt(&promotion.name, &basket, &format, payment.as_ref())?;
     }
 
-    pub fn total(&self) -> Currency {
-        let total = self
-            .products
-            .iter()
-            .map(|(product, quantity)| {
-                for deal in &self.deals {
-                    if deal.product == product.name {
-                        return match deal.kind {
-                            DealKind::Buy1Get1Free => {
-                                Currency(quantity.div_ceil(2) * product.price.0)
-                            }
-                            DealKind::PercentageDiscount(percentage) => {
-                                Currency(quantity * product.price.0 * (100 - percentage) / 100)
-                            }
-                        };
-                    }
-                }
-
-                Currency(quantity * product.price.0)
+    let buy1get1free = promotions
+        .iter()
+        .find(|promotion| promotion.id == "DEAL1")
+        .ok_or("promotions.json: no promotion 'DEAL1'")?;
+
+    let stacked = [
+        Deal::builder("A0001")
+            .kind(DealKind::PercentageDiscount(Percentage::from_basis_points(
+                1250,
+            )?))
+            .stacking(Stacking::Stackable)
+            .build()?,
+        Deal::builder("A0001")
+            .kind(DealKind::FixedAmountOff {
+                amount: Money::new(100, catalog.currency()),
             })
-            .sum();
+            .stacking(Stacking::Stackable)
+            .priority(1)
+            .build()?,
+    ];
+    let mut basket = demo_basket(&catalog, rounding, order, tax.as_ref(), clock)?;
+
+    for deal in stacked {
+        basket.add_deal(deal)?;
+    }
 
-        total
+    print_receipt("12.5PercentAnd1Off", &basket, &format, payment.as_ref())?;
+
+    let mut basket = demo_basket(&catalog, rounding, order, tax.as_ref(), clock)?;
+
+    add_promotion(&mut basket, buy1get1free)?;
+    basket.scan("A0001")?;
+    basket.remove("A0001")?;
+    basket.set_quantity("A0002", 4)?;
+    basket.scan_weighted("B0001", 500)?;
+    basket.void_line("B0001")?;
+    basket.scan("A0001")?;
+    basket.undo()?;
+    basket.redo()?;
+    basket.undo()?;
+
+    print_receipt("Corrections", &basket, &format, payment.as_ref())?;
+
+    let log = serde_json::to_string(basket.events())?;
+    let events: Vec<Event> = serde_json::from_str(&log)?;
+    let mut basket = Basket::replay(Arc::clone(&catalog), &events)?;
+
+    basket.set_rounding(rounding);
+    basket.set_line_order(order);
+    configure(&mut basket, tax.as_ref(), clock);
+
+    print_receipt("Replayed", &basket, &format, payment.as_ref())?;
+
+    let bananas = Deal::builder("B0001")
+        .kind(DealKind::PercentageDiscount(Percentage::from_percent(20)?))
+        .build()?;
+    let mut basket = demo_basket(&catalog, rounding, order, tax.as_ref(), clock)?;
+
+    basket.scan_weighted("B0001", 1250)?;
+    basket.add_deal(bananas)?;
+
+    print_receipt("20PercentOffBananas", &basket, &format, payment.as_ref())?;
+
+    let group_deals = [
+        (
+            "Any2For15",
+            GroupDealKind::MixAndMatch {
+                group: catalog.category("featured"),
+                quantity: 2,
+                price: Money::new(1500, catalog.currency()),
+            },
+        ),
+        (
+            "3For2",
+            GroupDealKind::CheapestFree {
+                group: catalog.category("featured"),
+                quantity: 3,
+            },
+        ),
+        (
+            "BuyA0001GetA0002Free",
+            GroupDealKind::BuyGetFree {
+                buy: ProductGroup::new("A0001", ["A0001"]),
+                get: ProductGroup::new("A0002", ["A0002"]),
+            },
+        ),
+    ];
+
+    for (label, kind) in group_deals {
+        let deal = GroupDeal { kind, priority: 0 };
+        let mut basket = demo_basket(&catalog, rounding, order, tax.as_ref(), clock)?;
+
+        basket.add_group_deal(deal)?;
+
+        print_receipt(label, &basket, &format, payment.as_ref())?;
     }
-}
 
-lazy_static! {
-    static ref INVENTORY: HashMap<String, Product> = {
-        vec![
-            Product::new("A0001".to_string(), 1299),
-            Product::new("A0002".to_string(), 399),
-        ]
-        .into_iter()
-        .map(|p| (p.name.clone(), p))
-        .collect::<HashMap<String, Product>>()
-    };
-    static ref DEAL1: Deal = {
-        Deal {
-            product: "A0002".to_string(),
-            kind: DealKind::Buy1Get1Free,
-        }
-    };
-    static ref DEAL2: Deal = {
-        Deal {
-            product: "A0001".to_string(),
-            kind: DealKind::PercentageDiscount(10),
-        }
-    };
-}
+    let threshold_deals = [
+        (
+            "10PercentOver20",
+            ThresholdDeal {
+                threshold: Money::new(2000, catalog.currency()),
+                base: ThresholdBase::BeforeDiscounts,
+                reward: ThresholdReward::PercentageOff(Percentage::from_percent(10)?),
+            },
+        ),
+        (
+            "5OffOver15",
+            ThresholdDeal {
+                threshold: Money::new(1500, catalog.currency()),
+                base: ThresholdBase::AfterDiscounts,
+                reward: ThresholdReward::AmountOff(Money::new(500, catalog.currency())),
+            },
+        ),
+    ];
+
+    for (label, deal) in threshold_deals {
+        let mut basket = demo_basket(&catalog, rounding, order, tax.as_ref(), clock)?;
+
+        add_promotion(&mut basket, buy1get1free)?;
+        basket.add_threshold_deal(deal)?;
+
+        print_receipt(label, &basket, &format, payment.as_ref())?;
+    }
 
-fn main() {
-    let mut basket1 = Basket::new();
+    Ok(())
+}
 
-    let _ = basket1.scan("A0002");
-    let _ = basket1.scan("A0001");
-    let _ = basket1.scan("A0002");
+fn demo_basket(
+    catalog: &Arc<Catalog>,
+    rounding: Rounding,
+    order: LineOrder,
+    tax: Option<&Arc<TaxJurisdiction>>,
+    clock: Option<FixedClock>,
+) -> Result<Basket, BasketError> {
+    let mut basket = Basket::new(Arc::clone(catalog));
+
+    basket.set_rounding(rounding);
+    basket.set_line_order(order);
+    configure(&mut basket, tax, clock);
+    basket.scan("A0002")?;
+    basket.scan("A0001")?;
+    basket.scan("A0002")?;
+
+    Ok(basket)
+}
 
-    basket1.add_deal(&DEAL1);
+/// Makes every deal of `promotion`, or its rule, available to `basket`.
+fn add_promotion(basket: &mut Basket, promotion: &Promotion) -> Result<(), BasketError> {
+    for deal in &promotion.deals {
+        basket.add_deal(deal.clone())?;
+    }
+    if let Some(rule) = &promotion.rule {
+        basket.add_rule(rule.clone())?;
+    }
 
-    println!("Buy1Get1Free Total: {}", &basket1.total());
+    Ok(())
+}
 
-    let mut basket2 = Basket::new();
+fn configure(basket: &mut Basket, tax: Option<&Arc<TaxJurisdiction>>, clock: Option<FixedClock>) {
+    if let Some(jurisdiction) = tax {
+        basket.set_jurisdiction(Arc::clone(jurisdiction));
+    }
+    if let Some(clock) = clock {
+        basket.set_clock(clock);
+    }
+}
 
-    let _ = basket2.scan("A0002");
-    let _ = basket2.scan("A0001");
-    let _ = basket2.scan("A0002");
+fn print_receipt(
+    label: &str,
+    basket: &Basket,
+    format: &CurrencyFormat,
+    payment: Option<&(CurrencyCode, ExchangeRates)>,
+) -> Result<(), BasketError> {
+    println!("{label}");
+    print!("{}", basket.receipt()?.display_with(format));
+
+    if let Some((currency, rates)) = payment {
+        let total = basket.total_in(*currency, rates)?;
+
+        println!(
+            "Paid in {currency}: {} = {} ({})",
+            total.total.display_with(format),
+            total.converted.display_with(format),
+            total.rate
+        );
+    }
 
-    basket2.add_deal(&DEAL2);
+    println!();
 
-    println!("10Percent Total: {}", &basket2.total());
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::{Basket, Currency, DEAL1, DEAL2};
+    use bitside_coding_challenge::{
+        basket::LineOrder, clock::FixedClock, deal::Rounding, money::CurrencyCode,
+    };
 
-    #[test]
-    fn test_total_without_products() {
-        let basket = Basket::new();
+    use crate::Options;
 
-        assert_eq!(Currency(0), basket.total());
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
     }
 
     #[test]
-    fn test_total_with_products() {
-        let mut basket = Basket::new();
-
-        let _ = basket.scan("A0001");
-        let _ = basket.scan("A0002");
-
-        assert_eq!(Currency(1698), basket.total());
+    fn test_parse_options() {
+        let options = parse(&[
+            "catalog.json",
+            "--at",
+            "2020-08-01",
+            "--pay-in=USD",
+            "--order",
+            "sku",
+            "--jurisdiction",
+            "jurisdictions/de.json",
+        ])
+        .unwrap();
+
+        assert_eq!("catalog.json", options.catalog);
+        assert_eq!(Some(CurrencyCode::Usd), options.payment);
+        assert_eq!(LineOrder::Sku, options.order);
+        assert_eq!(Rounding::default(), options.rounding);
+        assert_eq!(
+            Some("jurisdictions/de.json".to_string()),
+            options.jurisdiction
+        );
+        assert_eq!(
+            Some(FixedClock("2020-08-01T00:00:00".parse().unwrap())),
+            options.clock
+        );
+        assert_eq!("catalog.csv", parse(&[]).unwrap().catalog);
     }
 
     #[test]
-    fn test_deal1() {
-        let mut basket = Basket::new();
-
-        let _ = basket.scan("A0002");
-        let _ = basket.scan("A0001");
-        let _ = basket.scan("A0002");
-
-        basket.add_deal(&DEAL1);
-
-        assert_eq!(Currency(1698), basket.total());
-    }
-
-    #[test]
-    fn test_deal2() {
-        let mut basket = Basket::new();
-
-        let _ = basket.scan("A0002");
-        let _ = basket.scan("A0001");
-        let _ = basket.scan("A0002");
-
-        basket.add_deal(&DEAL2);
-
-        assert_eq!(Currency(1967), basket.total());
+    fn test_parse_invalid_options() {
+        assert_eq!(
+            "unknown option '--tax'",
+            parse(&["--tax", "de.json"]).unwrap_err()
+        );
+        assert_eq!("option '--at' needs a value", parse(&["--at"]).unwrap_err());
+        assert_eq!(
+            "unexpected argument 'de-DE'",
+            parse(&["catalog.csv", "de-DE"]).unwrap_err()
+        );
+        assert_eq!(
+            "unsupported locale 'xx-XX'",
+            parse(&["--locale", "xx-XX"]).unwrap_err()
+        );
     }
 }
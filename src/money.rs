@@ -0,0 +1,554 @@
+//! Amounts of money in minor units, and how they are formatted.
+
+use std::{fmt::Display, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+/// ISO 4217 currency codes supported by the pricing engine.
+#[derive(Debug, Default, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CurrencyCode {
+    #[default]
+    Eur,
+    Usd,
+    Gbp,
+    Chf,
+    Sek,
+    Jpy,
+    Bhd,
+    Kwd,
+}
+
+/// An amount of money in the minor units of its currency.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Money {
+    pub amount: u64,
+    pub currency: CurrencyCode,
+}
+
+/// Why an operation on [`Money`] failed.
+#[derive(Debug, PartialEq)]
+pub enum MoneyError {
+    CurrencyMismatch {
+        expected: CurrencyCode,
+        found: CurrencyCode,
+    },
+    Overflow,
+}
+
+/// How an exact amount is rounded to a whole minor unit.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum RoundingMode {
+    /// Halves are rounded up, e.g. 2.5 cents becomes 3.
+    HalfUp,
+    /// Halves are rounded to the nearest even minor unit, also known as banker's rounding.
+    HalfEven,
+    /// Always rounds down, in the store's favour when applied to discounted prices.
+    #[default]
+    Floor,
+    /// Always rounds up, in the customer's favour when applied to discounted prices.
+    Ceil,
+}
+
+/// Where the currency symbol goes relative to the amount.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolPosition {
+    /// `€12.05`
+    Prefix,
+    /// `12,05 €`, separated from the amount by a space.
+    Suffix,
+}
+
+/// Describes how a [`Money`] amount is rendered for a particular market.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CurrencyFormat {
+    pub show_symbol: bool,
+    pub symbol_position: SymbolPosition,
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+}
+
+/// A [`Money`] amount paired with the [`CurrencyFormat`] to display it with.
+pub struct Formatted<'a> {
+    money: &'a Money,
+    format: &'a CurrencyFormat,
+}
+
+impl CurrencyCode {
+    /// The number of decimal places of the minor unit, e.g. 2 for cents.
+    pub fn exponent(self) -> u32 {
+        match self {
+            CurrencyCode::Jpy => 0,
+            CurrencyCode::Bhd | CurrencyCode::Kwd => 3,
+            _ => 2,
+        }
+    }
+
+    /// The symbol amounts in the currency are written with, e.g. `€`.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            CurrencyCode::Eur => "€",
+            CurrencyCode::Usd => "$",
+            CurrencyCode::Gbp => "£",
+            CurrencyCode::Chf => "CHF",
+            CurrencyCode::Sek => "kr",
+            CurrencyCode::Jpy => "¥",
+            CurrencyCode::Bhd => "BD",
+            CurrencyCode::Kwd => "KD",
+        }
+    }
+}
+
+impl Display for CurrencyCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CurrencyCode::Eur => "EUR",
+            CurrencyCode::Usd => "USD",
+            CurrencyCode::Gbp => "GBP",
+            CurrencyCode::Chf => "CHF",
+            CurrencyCode::Sek => "SEK",
+            CurrencyCode::Jpy => "JPY",
+            CurrencyCode::Bhd => "BHD",
+            CurrencyCode::Kwd => "KWD",
+        })
+    }
+}
+
+impl FromStr for CurrencyCode {
+    type Err = String;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        match code {
+            "EUR" => Ok(CurrencyCode::Eur),
+            "USD" => Ok(CurrencyCode::Usd),
+            "GBP" => Ok(CurrencyCode::Gbp),
+            "CHF" => Ok(CurrencyCode::Chf),
+            "SEK" => Ok(CurrencyCode::Sek),
+            "JPY" => Ok(CurrencyCode::Jpy),
+            "BHD" => Ok(CurrencyCode::Bhd),
+            "KWD" => Ok(CurrencyCode::Kwd),
+            _ => Err(format!("unknown currency code '{code}'")),
+        }
+    }
+}
+
+impl RoundingMode {
+    /// Divides `numerator` by `denominator` and rounds the quotient, returning `None` if
+    /// `denominator` is zero.
+    pub fn div(self, numerator: u128, denominator: u128) -> Option<u128> {
+        let quotient = numerator.checked_div(denominator)?;
+        let remainder = numerator % denominator;
+        // Compares the remainder to half the denominator without overflowing.
+        let half = remainder.cmp(&(denominator - remainder));
+
+        let round_up = match self {
+            RoundingMode::HalfUp => half.is_ge(),
+            RoundingMode::HalfEven => half.is_gt() || (half.is_eq() && quotient % 2 == 1),
+            RoundingMode::Floor => false,
+            RoundingMode::Ceil => remainder > 0,
+        };
+
+        Some(quotient + u128::from(round_up))
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "half-up" => Ok(RoundingMode::HalfUp),
+            "half-even" => Ok(RoundingMode::HalfEven),
+            "floor" => Ok(RoundingMode::Floor),
+            "ceil" => Ok(RoundingMode::Ceil),
+            _ => Err(format!("unknown rounding mode '{mode}'")),
+        }
+    }
+}
+
+impl Display for MoneyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch { expected, found } => {
+                write!(f, "cannot combine {found} with {expected}")
+            }
+            MoneyError::Overflow => f.write_str("arithmetic overflow"),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+impl Money {
+    /// An amount of `amount` minor units of `currency`, e.g. cents for euros.
+    pub fn new(amount: u64, currency: CurrencyCode) -> Self {
+        Self { amount, currency }
+    }
+
+    /// No money at all, in `currency`.
+    pub fn zero(currency: CurrencyCode) -> Self {
+        Self::new(0, currency)
+    }
+
+    /// Adds two amounts, failing if they are in different currencies.
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch {
+                expected: self.currency,
+                found: other.currency,
+            });
+        }
+
+        self.amount
+            .checked_add(other.amount)
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Adds up `amounts`, which must all be in `currency`.
+    pub fn checked_sum(
+        currency: CurrencyCode,
+        amounts: impl IntoIterator<Item = Money>,
+    ) -> Result<Money, MoneyError> {
+        amounts
+            .into_iter()
+            .try_fold(Money::zero(currency), Money::checked_add)
+    }
+
+    /// Subtracts `other`, stopping at zero rather than going negative.
+    pub fn saturating_sub(self, other: Money) -> Result<Money, MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch {
+                expected: self.currency,
+                found: other.currency,
+            });
+        }
+
+        Ok(Money::new(
+            self.amount.saturating_sub(other.amount),
+            self.currency,
+        ))
+    }
+
+    /// Multiplies the amount by `factor`, failing if the result does not fit.
+    pub fn checked_mul(self, factor: u32) -> Result<Money, MoneyError> {
+        self.amount
+            .checked_mul(u64::from(factor))
+            .map(|amount| Money::new(amount, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Multiplies by `factor`, stopping at the largest representable amount.
+    pub fn saturating_mul(self, factor: u32) -> Money {
+        Money::new(self.amount.saturating_mul(u64::from(factor)), self.currency)
+    }
+
+    /// Multiplies by `numerator / denominator`, rounding down to the nearest minor unit.
+    pub fn checked_mul_div(self, numerator: u32, denominator: u32) -> Result<Money, MoneyError> {
+        self.checked_mul_div_rounded(numerator, denominator, RoundingMode::Floor)
+    }
+
+    /// Multiplies by `numerator / denominator`, rounding to a minor unit with `mode`.
+    pub fn checked_mul_div_rounded(
+        self,
+        numerator: u32,
+        denominator: u32,
+        mode: RoundingMode,
+    ) -> Result<Money, MoneyError> {
+        mode.div(
+            u128::from(self.amount) * u128::from(numerator),
+            u128::from(denominator),
+        )
+        .and_then(|amount| amount.try_into().ok())
+        .map(|amount| Money::new(amount, self.currency))
+        .ok_or(MoneyError::Overflow)
+    }
+
+    /// Splits the amount into parts proportional to `weights` that add up to exactly the
+    /// amount. The minor units lost to rounding go to the parts with the largest weights.
+    ///
+    /// Every part is zero if every weight is.
+    pub fn allocate(self, weights: &[u64]) -> Vec<Money> {
+        let total: u128 = weights.iter().map(|&weight| u128::from(weight)).sum();
+        if total == 0 {
+            return vec![Money::zero(self.currency); weights.len()];
+        }
+
+        // Each part is at most the amount, so it fits, and so does their sum.
+        let mut parts: Vec<u64> = weights
+            .iter()
+            .map(|&weight| (u128::from(self.amount) * u128::from(weight) / total) as u64)
+            .collect();
+        let mut left = self.amount - parts.iter().sum::<u64>();
+
+        let mut largest_first: Vec<usize> = (0..weights.len()).collect();
+        largest_first.sort_by_key(|&index| std::cmp::Reverse(weights[index]));
+        for index in largest_first {
+            if left == 0 {
+                break;
+            }
+            parts[index] += 1;
+            left -= 1;
+        }
+
+        parts
+            .into_iter()
+            .map(|amount| Money::new(amount, self.currency))
+            .collect()
+    }
+
+    /// Formats the amount according to `format`.
+    pub fn display_with<'a>(&'a self, format: &'a CurrencyFormat) -> Formatted<'a> {
+        Formatted {
+            money: self,
+            format,
+        }
+    }
+}
+
+impl Default for CurrencyFormat {
+    /// No symbol, `.` as decimal separator and no grouping, e.g. `1234.05`.
+    fn default() -> Self {
+        Self {
+            show_symbol: false,
+            symbol_position: SymbolPosition::Prefix,
+            decimal_separator: '.',
+            thousands_separator: None,
+        }
+    }
+}
+
+impl CurrencyFormat {
+    /// Returns the format customers expect for a locale tag such as `en-US` or `de-DE`.
+    pub fn for_locale(locale: &str) -> Option<Self> {
+        let (symbol_position, decimal_separator, thousands_separator) = match locale {
+            "en-US" | "en-GB" | "en-IE" => (SymbolPosition::Prefix, '.', ','),
+            "de-DE" | "es-ES" | "it-IT" | "nl-NL" => (SymbolPosition::Suffix, ',', '.'),
+            "fr-FR" | "sv-SE" => (SymbolPosition::Suffix, ',', ' '),
+            "de-CH" => (SymbolPosition::Prefix, '.', '\''),
+            _ => return None,
+        };
+
+        Some(Self {
+            show_symbol: true,
+            symbol_position,
+            decimal_separator,
+            thousands_separator: Some(thousands_separator),
+        })
+    }
+}
+
+impl Display for Money {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display_with(&CurrencyFormat::default()).fmt(f)
+    }
+}
+
+impl Display for Formatted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format = self.format;
+        let exponent = self.money.currency.exponent();
+        let divisor = 10u64.pow(exponent);
+        let units = (self.money.amount / divisor).to_string();
+
+        let mut amount = String::new();
+        for (index, digit) in units.chars().enumerate() {
+            if let Some(separator) = format.thousands_separator {
+                if index > 0 && (units.len() - index).is_multiple_of(3) {
+                    amount.push(separator);
+                }
+            }
+            amount.push(digit);
+        }
+        if exponent > 0 {
+            amount.push(format.decimal_separator);
+            amount.push_str(&format!(
+                "{:0width$}",
+                self.money.amount % divisor,
+                width = exponent as usize
+            ));
+        }
+
+        let symbol = self.money.currency.symbol();
+        match format.symbol_position {
+            _ if !format.show_symbol => f.write_str(&amount),
+            SymbolPosition::Prefix => write!(f, "{symbol}{amount}"),
+            SymbolPosition::Suffix => write!(f, "{amount} {symbol}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use crate::{
+        money::{CurrencyCode, CurrencyFormat, Money, MoneyError, RoundingMode, SymbolPosition},
+        test_support::eur,
+    };
+
+    #[test]
+    fn test_display_pads_cents() {
+        assert_eq!("12.05", eur(1205).to_string());
+        assert_eq!("0.00", eur(0).to_string());
+        assert_eq!("1234567.89", eur(123456789).to_string());
+    }
+
+    #[test]
+    fn test_display_uses_currency_exponent() {
+        assert_eq!("1205", Money::new(1205, CurrencyCode::Jpy).to_string());
+        assert_eq!("1.205", Money::new(1205, CurrencyCode::Bhd).to_string());
+        assert_eq!("0.050", Money::new(50, CurrencyCode::Kwd).to_string());
+    }
+
+    #[test]
+    fn test_display_with_locale() {
+        let us = CurrencyFormat::for_locale("en-US").unwrap();
+        assert_eq!(
+            "$1,234.05",
+            Money::new(123405, CurrencyCode::Usd)
+                .display_with(&us)
+                .to_string()
+        );
+
+        let de = CurrencyFormat::for_locale("de-DE").unwrap();
+        assert_eq!("1.234,05 €", eur(123405).display_with(&de).to_string());
+
+        assert_eq!(None, CurrencyFormat::for_locale("xx-XX"));
+    }
+
+    #[test]
+    fn test_display_with_custom_format() {
+        let format = CurrencyFormat {
+            show_symbol: true,
+            symbol_position: SymbolPosition::Prefix,
+            decimal_separator: ',',
+            thousands_separator: Some('.'),
+        };
+
+        assert_eq!("€1.234,05", eur(123405).display_with(&format).to_string());
+        assert_eq!("€999,99", eur(99999).display_with(&format).to_string());
+        assert_eq!(
+            "€1.000.000,00",
+            eur(100000000).display_with(&format).to_string()
+        );
+    }
+
+    #[test]
+    fn test_checked_add_rejects_mixed_currencies() {
+        assert_eq!(Ok(eur(300)), eur(100).checked_add(eur(200)));
+        assert_eq!(
+            Err(MoneyError::CurrencyMismatch {
+                expected: CurrencyCode::Eur,
+                found: CurrencyCode::Usd
+            }),
+            eur(100).checked_add(Money::new(200, CurrencyCode::Usd))
+        );
+        assert_eq!(Err(MoneyError::Overflow), eur(u64::MAX).checked_add(eur(1)));
+    }
+
+    #[test]
+    fn test_currency_code_round_trip() {
+        let code: CurrencyCode = "BHD".parse().unwrap();
+
+        assert_eq!(CurrencyCode::Bhd, code);
+        assert_eq!("BHD", code.to_string());
+        assert!("XYZ".parse::<CurrencyCode>().is_err());
+    }
+
+    #[test]
+    fn test_rounding_modes() {
+        let cases = [
+            // (numerator, denominator, half-up, half-even, floor, ceil)
+            (25, 10, 3, 2, 2, 3),
+            (35, 10, 4, 4, 3, 4),
+            (24, 10, 2, 2, 2, 3),
+            (26, 10, 3, 3, 2, 3),
+            (20, 10, 2, 2, 2, 2),
+        ];
+
+        for (numerator, denominator, half_up, half_even, floor, ceil) in cases {
+            let div = |mode: RoundingMode| mode.div(numerator, denominator).unwrap();
+
+            assert_eq!(half_up, div(RoundingMode::HalfUp));
+            assert_eq!(half_even, div(RoundingMode::HalfEven));
+            assert_eq!(floor, div(RoundingMode::Floor));
+            assert_eq!(ceil, div(RoundingMode::Ceil));
+        }
+
+        assert_eq!(None, RoundingMode::HalfUp.div(1, 0));
+    }
+
+    #[test]
+    fn test_allocate() {
+        let amounts = |parts: Vec<Money>| -> Vec<u64> { parts.iter().map(|m| m.amount).collect() };
+
+        assert_eq!(vec![34, 33, 33], amounts(eur(100).allocate(&[1, 1, 1])));
+        assert_eq!(vec![25, 0, 75], amounts(eur(100).allocate(&[100, 0, 300])));
+        assert_eq!(vec![0, 0], amounts(eur(100).allocate(&[0, 0])));
+    }
+
+    fn rounding_mode() -> impl Strategy<Value = RoundingMode> {
+        prop_oneof![
+            Just(RoundingMode::HalfUp),
+            Just(RoundingMode::HalfEven),
+            Just(RoundingMode::Floor),
+            Just(RoundingMode::Ceil),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_checked_mul_is_exact_or_overflows(amount: u64, factor: u32) {
+            let expected = u128::from(amount) * u128::from(factor);
+
+            match eur(amount).checked_mul(factor) {
+                Ok(product) => prop_assert_eq!(expected, u128::from(product.amount)),
+                Err(error) => {
+                    prop_assert_eq!(MoneyError::Overflow, error);
+                    prop_assert!(expected > u128::from(u64::MAX));
+                }
+            }
+        }
+
+        #[test]
+        fn prop_allocate_adds_up(
+            amount: u64,
+            weights in proptest::collection::vec(0..=u64::MAX, 1..8),
+        ) {
+            let parts = eur(amount).allocate(&weights);
+            let sum: u128 = parts.iter().map(|part| u128::from(part.amount)).sum();
+
+            prop_assert_eq!(weights.len(), parts.len());
+            if weights.iter().any(|&weight| weight > 0) {
+                prop_assert_eq!(u128::from(amount), sum);
+            }
+        }
+
+        #[test]
+        fn prop_checked_mul_div_rounds_to_a_neighbour(
+            amount: u64,
+            numerator in 0..=100u32,
+            denominator in 1..=100u32,
+            mode in rounding_mode(),
+        ) {
+            let exact = u128::from(amount) * u128::from(numerator);
+            let floor = exact / u128::from(denominator);
+
+            match eur(amount).checked_mul_div_rounded(numerator, denominator, mode) {
+                Ok(result) => {
+                    let result = u128::from(result.amount);
+
+                    prop_assert!(result == floor || result == floor + 1);
+                    if exact % u128::from(denominator) == 0 {
+                        prop_assert_eq!(floor, result);
+                    }
+                }
+                Err(error) => {
+                    prop_assert_eq!(MoneyError::Overflow, error);
+                    prop_assert!(floor >= u128::from(u64::MAX));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,320 @@
+//! Finds the cheapest way to apply a product's deals to a line.
+
+use crate::{
+    basket::BasketError,
+    catalog::Unit,
+    deal::{self, Deal, Rounding, Stacking},
+    money::Money,
+};
+
+/// The default bound on the number of steps the optimizer may take for a single product.
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+/// A number of units of a product priced together under the same deals.
+#[derive(Debug)]
+pub struct Allocation<'d> {
+    pub deals: Vec<&'d Deal>,
+    pub quantity: u32,
+    pub amount: Money,
+}
+
+/// Splits `quantity` units of a product priced at `price` per `unit` between its deals so that
+/// the customer pays as little as possible. Percentage discounts are rounded with `rounding`.
+///
+/// Every exclusive deal and the chain of all stackable deals are candidates, and each unit is
+/// priced under at most one candidate. The search takes roughly `candidates * quantity² / 2`
+/// steps; if that exceeds `max_steps`, the cheapest single candidate for the whole line is
+/// used instead. Ties are resolved in favour of the order chosen by [`deal::resolve`].
+///
+/// Products sold by weight or volume are always priced as a whole line, since splitting a
+/// weight between deals would not mean anything to the customer.
+pub fn optimize<'d>(
+    price: Money,
+    unit: Unit,
+    quantity: u32,
+    deals: &[&'d Deal],
+    rounding: Rounding,
+    max_steps: usize,
+) -> Result<Vec<Allocation<'d>>, BasketError> {
+    let candidates = candidates(deals);
+    let units = quantity as usize;
+
+    let steps = candidates
+        .len()
+        .saturating_mul(units.saturating_mul(units + 1) / 2);
+    if unit.is_measured() || steps > max_steps {
+        return whole_line(price, unit, quantity, candidates, rounding);
+    }
+
+    // costs[i][n] is the price of n units under candidate i.
+    let costs = candidates
+        .iter()
+        .map(|candidate| {
+            (0..=quantity)
+                .map(|n| cost(price, unit, n, candidate, rounding))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // best[j] is the cheapest price of j units using the candidates after i, and
+    // choices[i][j] is how many of those units candidate i takes.
+    let mut best = vec![None; units + 1];
+    best[0] = Some(Money::zero(price.currency));
+    let mut choices = vec![vec![0; units + 1]; candidates.len()];
+
+    for (i, costs) in costs.iter().enumerate().rev() {
+        let mut next = vec![None; units + 1];
+
+        for j in 0..=units {
+            for n in (0..=j).rev() {
+                let Some(rest) = best[j - n] else {
+                    continue;
+                };
+                let amount = rest.checked_add(costs[n])?;
+
+                if next[j].is_none_or(|current: Money| amount.amount < current.amount) {
+                    next[j] = Some(amount);
+                    choices[i][j] = n;
+                }
+            }
+        }
+
+        best = next;
+    }
+
+    let mut remaining = units;
+    let mut allocations = Vec::new();
+
+    for (i, (candidate, costs)) in candidates.into_iter().zip(&costs).enumerate() {
+        let n = choices[i][remaining];
+        remaining -= n;
+
+        if n > 0 {
+            allocations.push(Allocation {
+                deals: candidate,
+                quantity: n as u32,
+                amount: costs[n],
+            });
+        }
+    }
+
+    Ok(allocations)
+}
+
+/// Lists the ways units can be priced, in the order [`deal::resolve`] prefers them, followed
+/// by pricing without any deal.
+fn candidates<'d>(deals: &[&'d Deal]) -> Vec<Vec<&'d Deal>> {
+    let mut ranked = deals.to_vec();
+    ranked.sort_by_key(|deal| deal.priority);
+
+    let stackable: Vec<&Deal> = ranked
+        .iter()
+        .copied()
+        .filter(|deal| deal.stacking == Stacking::Stackable)
+        .collect();
+
+    let mut candidates = vec![deal::resolve(ranked.iter().copied())];
+    for deal in ranked {
+        let candidate = match deal.stacking {
+            Stacking::Exclusive => vec![deal],
+            Stacking::Stackable => stackable.clone(),
+        };
+
+        if !candidates.iter().any(|other| same_deals(other, &candidate)) {
+            candidates.push(candidate);
+        }
+    }
+    if !candidates.iter().any(Vec::is_empty) {
+        candidates.push(Vec::new());
+    }
+
+    candidates
+}
+
+fn same_deals(a: &[&Deal], b: &[&Deal]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| std::ptr::eq(*a, *b))
+}
+
+fn cost(
+    price: Money,
+    unit: Unit,
+    quantity: u32,
+    deals: &[&Deal],
+    rounding: Rounding,
+) -> Result<Money, BasketError> {
+    if quantity == 0 {
+        return Ok(Money::zero(price.currency));
+    }
+
+    deals
+        .iter()
+        .try_fold(unit.price(price, quantity)?, |amount, deal| {
+            if unit.is_measured() {
+                deal.apply_measured(amount, quantity, unit, rounding)
+            } else {
+                deal.apply(amount, quantity, rounding)
+            }
+        })
+}
+
+fn whole_line<'d>(
+    price: Money,
+    unit: Unit,
+    quantity: u32,
+    candidates: Vec<Vec<&'d Deal>>,
+    rounding: Rounding,
+) -> Result<Vec<Allocation<'d>>, BasketError> {
+    let mut best: Option<Allocation> = None;
+
+    for candidate in candidates {
+        let amount = cost(price, unit, quantity, &candidate, rounding)?;
+
+        if best
+            .as_ref()
+            .is_none_or(|best| amount.amount < best.amount.amount)
+        {
+            best = Some(Allocation {
+                deals: candidate,
+                quantity,
+                amount,
+            });
+        }
+    }
+
+    Ok(best.into_iter().filter(|best| best.quantity > 0).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::Unit,
+        deal::{self, Deal, DealKind, Percentage, Rounding, Stacking},
+        money::{CurrencyCode, Money},
+        optimizer::{optimize, DEFAULT_MAX_STEPS},
+        validity::Validity,
+    };
+
+    const PRICE: Money = Money {
+        amount: 399,
+        currency: CurrencyCode::Eur,
+    };
+
+    fn deal(kind: DealKind, stacking: Stacking, priority: u32) -> Deal {
+        Deal {
+            product: "A0002".to_string(),
+            kind,
+            stacking,
+            priority,
+            validity: Validity::default(),
+        }
+    }
+
+    fn percent_off(percent: u32) -> DealKind {
+        DealKind::PercentageDiscount(Percentage::from_percent(percent).unwrap())
+    }
+
+    /// What the basket charged before the optimizer: the deals picked by precedence, applied
+    /// to the whole line.
+    fn first_match(quantity: u32, deals: &[Deal]) -> u64 {
+        deal::resolve(deals)
+            .into_iter()
+            .try_fold(PRICE.checked_mul(quantity).unwrap(), |amount, deal| {
+                deal.apply(amount, quantity, Rounding::default())
+            })
+            .unwrap()
+            .amount
+    }
+
+    fn optimized(quantity: u32, deals: &[Deal], max_steps: usize) -> u64 {
+        let deals: Vec<&Deal> = deals.iter().collect();
+        let allocations = optimize(
+            PRICE,
+            Unit::Piece,
+            quantity,
+            &deals,
+            Rounding::default(),
+            max_steps,
+        )
+        .unwrap();
+
+        assert_eq!(
+            quantity,
+            allocations
+                .iter()
+                .map(|allocation| allocation.quantity)
+                .sum::<u32>()
+        );
+
+        allocations
+            .iter()
+            .map(|allocation| allocation.amount.amount)
+            .sum()
+    }
+
+    #[test]
+    fn test_picks_cheapest_exclusive_deal() {
+        let deals = [
+            deal(percent_off(10), Stacking::Exclusive, 0),
+            deal(DealKind::Buy1Get1Free, Stacking::Exclusive, 0),
+        ];
+
+        assert_eq!(1436, first_match(4, &deals));
+        assert_eq!(798, optimized(4, &deals, DEFAULT_MAX_STEPS));
+    }
+
+    #[test]
+    fn test_considers_exclusive_deals_behind_stackable_ones() {
+        let deals = [
+            deal(percent_off(10), Stacking::Stackable, 0),
+            deal(DealKind::Buy1Get1Free, Stacking::Exclusive, 1),
+        ];
+
+        assert_eq!(1436, first_match(4, &deals));
+        assert_eq!(798, optimized(4, &deals, DEFAULT_MAX_STEPS));
+    }
+
+    #[test]
+    fn test_splits_units_between_deals() {
+        let deals = [
+            deal(DealKind::Buy1Get1Free, Stacking::Exclusive, 0),
+            deal(percent_off(10), Stacking::Exclusive, 0),
+        ];
+
+        // Two units under Buy1Get1Free and the odd one out at 10% off.
+        assert_eq!(798, first_match(3, &deals));
+        assert_eq!(758, optimized(3, &deals, DEFAULT_MAX_STEPS));
+    }
+
+    #[test]
+    fn test_without_deals() {
+        assert_eq!(1197, optimized(3, &[], DEFAULT_MAX_STEPS));
+    }
+
+    #[test]
+    fn test_falls_back_to_whole_line_when_over_budget() {
+        let deals = [
+            deal(percent_off(10), Stacking::Exclusive, 0),
+            deal(DealKind::Buy1Get1Free, Stacking::Exclusive, 0),
+        ];
+
+        assert_eq!(1795, first_match(5, &deals));
+        assert_eq!(1197, optimized(5, &deals, 1));
+        assert_eq!(1157, optimized(5, &deals, DEFAULT_MAX_STEPS));
+    }
+
+    #[test]
+    fn test_never_worse_than_first_match() {
+        let deals = [
+            deal(DealKind::Buy1Get1Free, Stacking::Stackable, 2),
+            deal(percent_off(15), Stacking::Exclusive, 0),
+            deal(percent_off(5), Stacking::Stackable, 1),
+            deal(percent_off(40), Stacking::Exclusive, 3),
+        ];
+
+        for quantity in 1..=25 {
+            assert!(
+                optimized(quantity, &deals, DEFAULT_MAX_STEPS) <= first_match(quantity, &deals)
+            );
+        }
+    }
+}
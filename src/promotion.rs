@@ -0,0 +1,504 @@
+//! Promotions defined in a configuration file and the deals they put on offer.
+
+use std::{collections::HashSet, fmt::Display, fs, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    catalog::{json_entry_line, Catalog},
+    deal::{Deal, DealError, DealKind, Percentage, Stacking},
+    money::Money,
+    rule::Rule,
+    validity::Validity,
+};
+
+/// A promotion as marketing defines it: either the same deal on each of a list of products,
+/// or a [`Rule`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Promotion {
+    pub id: String,
+    pub name: String,
+    /// One deal per product the promotion targets, in the order they were listed.
+    pub deals: Vec<Deal>,
+    pub rule: Option<Rule>,
+}
+
+/// Why a promotions file could not be loaded.
+#[derive(Debug)]
+pub enum PromotionError {
+    Io(std::io::Error),
+    /// The entry starting on `line` is malformed or does not fit the catalog. `id` names the
+    /// promotion if the entry got as far as having one.
+    Invalid {
+        line: usize,
+        id: Option<String>,
+        reason: String,
+    },
+}
+
+/// One entry of a promotions file.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Record {
+    id: String,
+    name: String,
+    kind: Option<Kind>,
+    params: Option<Params>,
+    #[serde(default)]
+    skus: Vec<String>,
+    stacking: Option<Stacking>,
+    priority: Option<u32>,
+    validity: Option<Validity>,
+    rule: Option<String>,
+}
+
+/// The deal kinds a promotion can use, named as in the file.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Kind {
+    Buy1Get1Free,
+    PercentageDiscount,
+    BuyNGetMFree,
+    MultiBuyFixedPrice,
+    FixedAmountOff,
+}
+
+/// The parameters of every kind, of which each kind uses some. Amounts are in minor units of
+/// the catalog currency.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Params {
+    basis_points: Option<u32>,
+    buy: Option<u32>,
+    free: Option<u32>,
+    quantity: Option<u32>,
+    price: Option<u64>,
+    amount: Option<u64>,
+}
+
+impl Promotion {
+    /// Loads promotions from a JSON file, see [`Promotion::from_json`].
+    pub fn from_path(
+        path: impl AsRef<Path>,
+        catalog: &Catalog,
+    ) -> Result<Vec<Self>, PromotionError> {
+        Self::from_json(&fs::read_to_string(path)?, catalog)
+    }
+
+    /// Parses a JSON array of promotions and checks them against `catalog`.
+    ///
+    /// Each entry has a unique `"id"`, a `"name"`, a `"kind"` of deal with its `"params"`, and
+    /// the `"skus"` it applies to, which must all be in the catalog and sold in a way the deal
+    /// fits. `"stacking"` (`exclusive` by default), `"priority"` and `"validity"` are optional.
+    /// Instead of a kind and SKUs, an entry can give a `"rule"` in the [rule
+    /// language](crate::rule), optionally with a `"validity"`:
+    ///
+    /// ```json
+    /// [
+    ///     {
+    ///         "id": "soap-3-for-2",
+    ///         "name": "Soap: buy 2, get 1 free",
+    ///         "kind": "buy_n_get_m_free",
+    ///         "params": { "buy": 2, "free": 1 },
+    ///         "skus": ["A0002"],
+    ///         "validity": { "start": "2024-03-04T00:00:00", "end": "2024-03-11T00:00:00" }
+    ///     },
+    ///     {
+    ///         "id": "big-shop",
+    ///         "name": "5% off featured items over 50.00",
+    ///         "rule": "when category = \"featured\" and subtotal >= 50.00 then 5% off"
+    ///     }
+    /// ]
+    /// ```
+    ///
+    /// `percentage_discount` takes `basis_points`, `multi_buy_fixed_price` a `quantity` and a
+    /// `price`, and `fixed_amount_off` an `amount`, both in minor units.
+    pub fn from_json(input: &str, catalog: &Catalog) -> Result<Vec<Self>, PromotionError> {
+        let mut deserializer = serde_json::Deserializer::from_str(input);
+        let values: Vec<serde_json::Value> = Vec::deserialize(&mut deserializer)
+            .and_then(|values| deserializer.end().map(|_| values))
+            .map_err(|error| PromotionError::Invalid {
+                line: error.line(),
+                id: None,
+                reason: error.to_string(),
+            })?;
+
+        let mut promotions: Vec<Promotion> = Vec::new();
+        let mut ids = HashSet::new();
+
+        for (index, value) in values.into_iter().enumerate() {
+            let line = json_entry_line(input, index);
+            let id = value
+                .get("id")
+                .and_then(|id| id.as_str())
+                .map(str::to_string);
+            let invalid = |reason: String| PromotionError::Invalid {
+                line,
+                id: id.clone(),
+                reason,
+            };
+
+            let record = Record::deserialize(value).map_err(|error| invalid(error.to_string()))?;
+            if !ids.insert(record.id.clone()) {
+                return Err(invalid("the id is already used".to_string()));
+            }
+            promotions.push(record.promotion(catalog).map_err(invalid)?);
+        }
+
+        Ok(promotions)
+    }
+}
+
+impl Record {
+    fn promotion(self, catalog: &Catalog) -> Result<Promotion, String> {
+        let kind = match (self.kind, &self.rule) {
+            (Some(kind), None) => kind,
+            (None, Some(_)) => return self.rule_promotion(catalog),
+            (Some(_), Some(_)) => return Err("has both a kind and a rule".to_string()),
+            (None, None) => return Err("needs a kind or a rule".to_string()),
+        };
+        if self.skus.is_empty() {
+            return Err("no skus to apply to".to_string());
+        }
+
+        let kind = self.params.unwrap_or_default().kind(kind, catalog)?;
+        let deals = self
+            .skus
+            .iter()
+            .map(|sku| {
+                let product = catalog
+                    .get(sku)
+                    .ok_or_else(|| format!("sku '{sku}' is not in the catalog"))?;
+                if product.unit.is_measured() && kind.counts_units() {
+                    return Err(format!("sku '{sku}' is sold per {}", product.unit));
+                }
+
+                Deal::builder(sku)
+                    .kind(kind.clone())
+                    .stacking(self.stacking.unwrap_or(Stacking::Exclusive))
+                    .priority(self.priority.unwrap_or_default())
+                    .validity(self.validity.clone().unwrap_or_default())
+                    .build()
+                    .map_err(|error| error.to_string())
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Promotion {
+            id: self.id,
+            name: self.name,
+            deals,
+            rule: None,
+        })
+    }
+
+    fn rule_promotion(self, catalog: &Catalog) -> Result<Promotion, String> {
+        if !self.skus.is_empty()
+            || self.params.is_some()
+            || self.stacking.is_some()
+            || self.priority.is_some()
+        {
+            return Err("a rule takes no skus, params, stacking or priority".to_string());
+        }
+
+        let source = self.rule.unwrap_or_default();
+        let mut rule =
+            Rule::parse(&source, catalog.currency()).map_err(|error| format!("rule: {error}"))?;
+        rule.validity = self.validity.unwrap_or_default();
+        if rule.validity.is_empty() {
+            return Err(DealError::EmptyValidity.to_string());
+        }
+        if let Some(sku) = rule.skus().find(|sku| catalog.get(sku).is_none()) {
+            return Err(format!("sku '{sku}' is not in the catalog"));
+        }
+
+        Ok(Promotion {
+            id: self.id,
+            name: self.name,
+            deals: Vec::new(),
+            rule: Some(rule),
+        })
+    }
+}
+
+impl Params {
+    /// Builds a deal kind out of the parameters `kind` uses, rejecting any others.
+    fn kind(&self, kind: Kind, catalog: &Catalog) -> Result<DealKind, String> {
+        let money = |amount| Money::new(amount, catalog.currency());
+        let (deal, used): (DealKind, &[&str]) = match kind {
+            Kind::Buy1Get1Free => (DealKind::Buy1Get1Free, &[]),
+            Kind::PercentageDiscount => {
+                let basis_points = required("basis_points", self.basis_points)?;
+                let percentage = Percentage::from_basis_points(basis_points)
+                    .map_err(|error| error.to_string())?;
+
+                (DealKind::PercentageDiscount(percentage), &["basis_points"])
+            }
+            Kind::BuyNGetMFree => (
+                DealKind::BuyNGetMFree {
+                    buy: required("buy", self.buy)?,
+                    free: required("free", self.free)?,
+                },
+                &["buy", "free"],
+            ),
+            Kind::MultiBuyFixedPrice => (
+                DealKind::MultiBuyFixedPrice {
+                    quantity: required("quantity", self.quantity)?,
+                    price: money(required("price", self.price)?),
+                },
+                &["quantity", "price"],
+            ),
+            Kind::FixedAmountOff => (
+                DealKind::FixedAmountOff {
+                    amount: money(required("amount", self.amount)?),
+                },
+                &["amount"],
+            ),
+        };
+
+        let given = [
+            ("basis_points", self.basis_points.is_some()),
+            ("buy", self.buy.is_some()),
+            ("free", self.free.is_some()),
+            ("quantity", self.quantity.is_some()),
+            ("price", self.price.is_some()),
+            ("amount", self.amount.is_some()),
+        ];
+        match given
+            .into_iter()
+            .find(|(param, given)| *given && !used.contains(param))
+        {
+            Some((param, _)) => Err(format!("parameter '{param}' does not apply to {kind}")),
+            None => Ok(deal),
+        }
+    }
+}
+
+fn required<T>(param: &str, value: Option<T>) -> Result<T, String> {
+    value.ok_or_else(|| format!("missing parameter '{param}'"))
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Kind::Buy1Get1Free => "buy1_get1_free",
+            Kind::PercentageDiscount => "percentage_discount",
+            Kind::BuyNGetMFree => "buy_n_get_m_free",
+            Kind::MultiBuyFixedPrice => "multi_buy_fixed_price",
+            Kind::FixedAmountOff => "fixed_amount_off",
+        })
+    }
+}
+
+impl Display for PromotionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromotionError::Io(error) => write!(f, "could not read promotions: {error}"),
+            PromotionError::Invalid {
+                line,
+                id: Some(id),
+                reason,
+            } => write!(f, "line {line}: promotion '{id}': {reason}"),
+            PromotionError::Invalid {
+                line,
+                id: None,
+                reason,
+            } => write!(f, "line {line}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for PromotionError {}
+
+impl From<std::io::Error> for PromotionError {
+    fn from(error: std::io::Error) -> Self {
+        PromotionError::Io(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::Catalog,
+        deal::{DealKind, Percentage, Stacking},
+        money::{CurrencyCode, Money},
+        promotion::Promotion,
+    };
+
+    fn catalog() -> Catalog {
+        Catalog::from_csv(
+            "sku,name,price,currency,category,unit\n\
+             A0001,Water,1299,EUR,,\n\
+             A0002,Soap,399,EUR,,\n\
+             B0001,Bananas,199,EUR,,kg\n",
+        )
+        .unwrap()
+    }
+
+    fn error(input: &str) -> String {
+        Promotion::from_json(input, &catalog())
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_from_json() {
+        let promotions = Promotion::from_json(
+            r#"[
+                {
+                    "id": "water-soap-2-for-7",
+                    "name": "Any 2 for 7.00",
+                    "kind": "multi_buy_fixed_price",
+                    "params": { "quantity": 2, "price": 700 },
+                    "skus": ["A0001", "A0002"],
+                    "priority": 1
+                },
+                {
+                    "id": "bananas-10-off",
+                    "name": "10% off bananas",
+                    "kind": "percentage_discount",
+                    "params": { "basis_points": 1000 },
+                    "skus": ["B0001"],
+                    "stacking": "stackable",
+                    "validity": { "start": "2024-03-04T00:00:00" }
+                }
+            ]"#,
+            &catalog(),
+        )
+        .unwrap();
+
+        assert_eq!(2, promotions.len());
+        assert_eq!(
+            vec!["A0001", "A0002"],
+            promotions[0]
+                .deals
+                .iter()
+                .map(|deal| deal.product.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            DealKind::MultiBuyFixedPrice {
+                quantity: 2,
+                price: Money::new(700, CurrencyCode::Eur),
+            },
+            promotions[0].deals[1].kind
+        );
+        assert_eq!(1, promotions[0].deals[1].priority);
+
+        let bananas = &promotions[1].deals[0];
+        assert_eq!(
+            DealKind::PercentageDiscount(Percentage::from_percent(10).unwrap()),
+            bananas.kind
+        );
+        assert_eq!(Stacking::Stackable, bananas.stacking);
+        assert!(bananas.validity.start.is_some());
+    }
+
+    #[test]
+    fn test_rule_promotions() {
+        let promotions = Promotion::from_json(
+            r#"[{ "id": "pairs", "name": "Pairs", "rule": "when quantity >= 2 then 5% off" }]"#,
+            &catalog(),
+        )
+        .unwrap();
+
+        assert!(promotions[0].deals.is_empty());
+        assert_eq!(
+            "when quantity >= 2 then 5% off",
+            promotions[0].rule.as_ref().unwrap().to_string()
+        );
+
+        assert_eq!(
+            "line 1: promotion 'pairs': rule: column 15: expected a comparison such as '=' or '>='",
+            error(r#"[{ "id": "pairs", "name": "Pairs", "rule": "when quantity 2 then 5% off" }]"#)
+        );
+        assert_eq!(
+            "line 1: promotion 'water': sku 'A0003' is not in the catalog",
+            error(
+                r#"[{ "id": "water", "name": "Water", "rule": "when sku = \"A0003\" then 5% off" }]"#
+            )
+        );
+        let happy_hour = Promotion::from_json(
+            r#"[{
+                "id": "happy-hour",
+                "name": "Happy hour",
+                "rule": "when quantity >= 2 then 5% off",
+                "validity": { "recurrence": { "from": "17:00:00", "until": "19:00:00" } }
+            }]"#,
+            &catalog(),
+        )
+        .unwrap();
+        let validity = &happy_hour[0].rule.as_ref().unwrap().validity;
+        assert!(validity.contains("2024-03-08T18:00:00".parse().unwrap()));
+        assert!(!validity.contains("2024-03-08T20:00:00".parse().unwrap()));
+
+        assert_eq!(
+            "line 1: promotion 'pairs': a rule takes no skus, params, stacking or priority",
+            error(
+                r#"[{ "id": "pairs", "name": "Pairs", "priority": 1, "rule": "when quantity >= 2 then 5% off" }]"#
+            )
+        );
+        assert_eq!(
+            "line 1: promotion 'pairs': has both a kind and a rule",
+            error(
+                r#"[{ "id": "pairs", "name": "Pairs", "kind": "buy1_get1_free", "rule": "when quantity >= 2 then 5% off" }]"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_from_json_points_at_entry() {
+        let entries = |second: &str| {
+            format!(
+                r#"[
+                    {{ "id": "soap", "name": "Soap", "kind": "buy1_get1_free", "skus": ["A0002"] }},
+                    {second}
+                ]"#
+            )
+        };
+
+        assert_eq!(
+            "line 3: promotion 'water': sku 'A0003' is not in the catalog",
+            error(&entries(
+                r#"{ "id": "water", "name": "Water", "kind": "buy1_get1_free", "skus": ["A0003"] }"#
+            ))
+        );
+        assert_eq!(
+            "line 3: promotion 'soap': the id is already used",
+            error(&entries(
+                r#"{ "id": "soap", "name": "Soap", "kind": "buy1_get1_free", "skus": ["A0001"] }"#
+            ))
+        );
+        assert_eq!(
+            "line 3: promotion 'bananas': sku 'B0001' is sold per kg",
+            error(&entries(
+                r#"{ "id": "bananas", "name": "Bananas", "kind": "buy1_get1_free", "skus": ["B0001"] }"#
+            ))
+        );
+    }
+
+    #[test]
+    fn test_from_json_checks_params() {
+        let entry = |kind: &str, params: &str| {
+            error(&format!(
+                r#"[{{ "id": "deal", "name": "Deal", "kind": "{kind}", "params": {params}, "skus": ["A0001"] }}]"#
+            ))
+        };
+
+        assert_eq!(
+            "line 1: promotion 'deal': missing parameter 'free'",
+            entry("buy_n_get_m_free", r#"{ "buy": 2 }"#)
+        );
+        assert_eq!(
+            "line 1: promotion 'deal': parameter 'price' does not apply to fixed_amount_off",
+            entry("fixed_amount_off", r#"{ "amount": 100, "price": 100 }"#)
+        );
+        assert_eq!(
+            "line 1: promotion 'deal': deal parameter 'quantity' must be at least 1",
+            entry(
+                "multi_buy_fixed_price",
+                r#"{ "quantity": 0, "price": 700 }"#
+            )
+        );
+        assert!(entry("half_price", "{}").starts_with("line 1: promotion 'deal': unknown variant"));
+    }
+}
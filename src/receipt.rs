@@ -0,0 +1,457 @@
+//! Itemized receipts and how they are rendered.
+
+use std::fmt::Display;
+
+use crate::{
+    catalog::Unit,
+    deal::DealKind,
+    group::GroupDeal,
+    money::{CurrencyFormat, Money},
+    tax::{PricingMode, TaxClass, TaxSummary},
+    threshold::ThresholdDeal,
+};
+
+/// Width of the text column of a rendered receipt; amounts are right-aligned after it.
+const TEXT_WIDTH: usize = 40;
+const AMOUNT_WIDTH: usize = 12;
+
+/// An itemized breakdown of how a basket's total was reached.
+#[derive(Debug, PartialEq)]
+pub struct Receipt {
+    pub lines: Vec<ReceiptLine>,
+    /// Savings from group deals, rules and threshold deals, which are not tied to a single line.
+    pub adjustments: Vec<Adjustment>,
+    /// The regular price of every item.
+    pub subtotal: Money,
+    pub savings: Money,
+    /// The tax charged at each rate after discounts, empty unless the basket has a tax policy.
+    pub taxes: Vec<TaxSummary>,
+    /// Whether `taxes` are included in the prices or added to the total.
+    pub pricing: PricingMode,
+    /// What the tax is called, e.g. `VAT`.
+    pub tax_label: String,
+    pub total: Money,
+}
+
+/// A product on a [`Receipt`], with what it would have cost and what was charged.
+#[derive(Debug, PartialEq)]
+pub struct ReceiptLine {
+    pub sku: String,
+    pub name: String,
+    /// Pieces, or grams or millilitres for products sold by weight or volume.
+    pub quantity: u32,
+    pub unit: Unit,
+    pub tax_class: TaxClass,
+    /// The price per `unit`.
+    pub unit_price: Money,
+    pub gross: Money,
+    /// The kinds of product deal applied to the line, empty if it was charged at the regular
+    /// price.
+    pub deals: Vec<DealKind>,
+    pub discount: Money,
+    pub net: Money,
+}
+
+/// A saving from a group deal, a rule or a threshold deal.
+#[derive(Debug, PartialEq)]
+pub struct Adjustment {
+    pub deal: AdjustmentDeal,
+    pub amount: Money,
+}
+
+/// The deal behind an [`Adjustment`], kept so it can be described in the format of the receipt.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdjustmentDeal {
+    Group(GroupDeal),
+    /// The action of a rule.
+    Rule(DealKind),
+    Threshold(ThresholdDeal),
+}
+
+/// A [`Receipt`] paired with the [`CurrencyFormat`] to render it with.
+pub struct FormattedReceipt<'a> {
+    receipt: &'a Receipt,
+    format: &'a CurrencyFormat,
+}
+
+impl Receipt {
+    /// Renders the receipt as text, formatting amounts according to `format`.
+    pub fn display_with<'a>(&'a self, format: &'a CurrencyFormat) -> FormattedReceipt<'a> {
+        FormattedReceipt {
+            receipt: self,
+            format,
+        }
+    }
+}
+
+impl AdjustmentDeal {
+    /// Describes the deal as it is shown on a receipt, formatting amounts according to
+    /// `format`.
+    pub fn describe(&self, format: &CurrencyFormat) -> String {
+        match self {
+            AdjustmentDeal::Group(deal) => deal.describe(format),
+            AdjustmentDeal::Rule(action) => action.describe(format),
+            AdjustmentDeal::Threshold(deal) => deal.describe(format),
+        }
+    }
+}
+
+impl Display for AdjustmentDeal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(&CurrencyFormat::default()))
+    }
+}
+
+impl FormattedReceipt<'_> {
+    fn row(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        text: &str,
+        sign: &str,
+        amount: &Money,
+    ) -> std::fmt::Result {
+        let amount = format!("{sign}{}", amount.display_with(self.format));
+
+        writeln!(f, "{text:<TEXT_WIDTH$}{amount:>AMOUNT_WIDTH$}")
+    }
+
+    /// One row per tax rate, e.g. `VAT 20% on €14.15`, followed by the tax.
+    fn taxes(&self, f: &mut std::fmt::Formatter<'_>, prefix: &str) -> std::fmt::Result {
+        let label = &self.receipt.tax_label;
+
+        for summary in &self.receipt.taxes {
+            let net = summary.net.display_with(self.format);
+            let text = match summary.rate {
+                None => format!("{prefix}{label} exempt on {net}"),
+                Some(rate) => format!("{prefix}{label} {rate} on {net}"),
+            };
+
+            self.row(f, &text, "", &summary.tax)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for FormattedReceipt<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let receipt = self.receipt;
+
+        for line in &receipt.lines {
+            let price = line.unit_price.display_with(self.format);
+
+            if line.unit.is_measured() {
+                let text = format!("{:<8}{}", line.sku, line.name);
+                self.row(f, &text, "", &line.gross)?;
+
+                let scale = line.unit.scale();
+                writeln!(
+                    f,
+                    "  {}.{:03} {} x {price}/{}",
+                    line.quantity / scale,
+                    line.quantity % scale,
+                    line.unit,
+                    line.unit
+                )?;
+            } else {
+                let text = format!(
+                    "{:<8}{:<20}{:>3} x {price}",
+                    line.sku, line.name, line.quantity
+                );
+                self.row(f, &text, "", &line.gross)?;
+            }
+
+            if !line.deals.is_empty() {
+                let deals: Vec<String> = line
+                    .deals
+                    .iter()
+                    .map(|deal| deal.describe(self.format))
+                    .collect();
+                self.row(f, &format!("  {}", deals.join(" + ")), "-", &line.discount)?;
+            }
+        }
+
+        writeln!(f, "{}", "-".repeat(TEXT_WIDTH + AMOUNT_WIDTH))?;
+
+        for adjustment in &receipt.adjustments {
+            let deal = adjustment.deal.describe(self.format);
+            self.row(f, &deal, "-", &adjustment.amount)?;
+        }
+
+        self.row(f, "Subtotal", "", &receipt.subtotal)?;
+        self.row(f, "Savings", "", &receipt.savings)?;
+
+        match receipt.pricing {
+            PricingMode::Exclusive => {
+                self.taxes(f, "")?;
+                self.row(f, "Total", "", &receipt.total)
+            }
+            PricingMode::Inclusive => {
+                self.row(f, "Total", "", &receipt.total)?;
+                self.taxes(f, "  incl. ")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        basket::{Basket, LineOrder},
+        catalog::{Catalog, ProductGroup, Unit},
+        deal::{Deal, DealKind, Percentage},
+        group::{GroupDeal, GroupDealKind},
+        money::CurrencyFormat,
+        receipt::{Adjustment, AdjustmentDeal, ReceiptLine},
+        tax::{PricingMode, TaxClass, TaxPolicy, TaxRates},
+        test_support::{buy1get1free, catalog, eur},
+    };
+
+    #[test]
+    fn test_receipt_lines() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+
+        let receipt = basket.receipt().unwrap();
+
+        assert_eq!(
+            ReceiptLine {
+                sku: "A0002".to_string(),
+                name: "Soap".to_string(),
+                quantity: 2,
+                unit: Unit::Piece,
+                tax_class: TaxClass::Standard,
+                unit_price: eur(399),
+                gross: eur(798),
+                deals: vec![DealKind::Buy1Get1Free],
+                discount: eur(399),
+                net: eur(399),
+            },
+            receipt.lines[0]
+        );
+        assert_eq!(eur(2097), receipt.subtotal);
+        assert_eq!(eur(399), receipt.savings);
+        assert_eq!(eur(1698), receipt.total);
+        assert_eq!(basket.total().unwrap(), receipt.total);
+    }
+
+    #[test]
+    fn test_line_order() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0003").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+        basket.scan("A0003").unwrap();
+        basket.set_quantity("A0001", 3).unwrap();
+
+        let skus = |basket: &Basket| -> Vec<String> {
+            basket
+                .receipt()
+                .unwrap()
+                .lines
+                .into_iter()
+                .map(|line| line.sku)
+                .collect()
+        };
+
+        assert_eq!(["A0003", "A0001", "A0002"], skus(&basket).as_slice());
+
+        basket.set_line_order(LineOrder::Sku);
+        assert_eq!(["A0001", "A0002", "A0003"], skus(&basket).as_slice());
+
+        basket.set_line_order(LineOrder::Name);
+        assert_eq!(["A0002", "A0003", "A0001"], skus(&basket).as_slice());
+    }
+
+    #[test]
+    fn test_receipt_adjustments() {
+        let catalog = catalog();
+        let deal = GroupDeal {
+            kind: GroupDealKind::BuyGetFree {
+                buy: ProductGroup::new("water", ["A0001"]),
+                get: ProductGroup::new("towel", ["A0003"]),
+            },
+            priority: 0,
+        };
+        let mut basket = Basket::new(Arc::clone(&catalog));
+
+        basket.scan("A0001").unwrap();
+        basket.scan("A0003").unwrap();
+        basket.add_group_deal(deal.clone()).unwrap();
+
+        let receipt = basket.receipt().unwrap();
+
+        assert_eq!(
+            vec![Adjustment {
+                deal: AdjustmentDeal::Group(deal),
+                amount: eur(500),
+            }],
+            receipt.adjustments
+        );
+        assert_eq!(
+            "Buy water get towel free",
+            receipt.adjustments[0].deal.to_string()
+        );
+        assert_eq!(eur(1299), receipt.total);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut basket = Basket::new(catalog());
+
+        basket.scan("A0002").unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+
+        let format = CurrencyFormat::for_locale("en-US").unwrap();
+
+        assert_eq!(
+            "\
+A0002   Soap                  2 x €3.99        €7.98
+  Buy 1 get 1 free                            -€3.99
+A0001   Water                 1 x €12.99      €12.99
+----------------------------------------------------
+Subtotal                                      €20.97
+Savings                                        €3.99
+Total                                         €16.98
+",
+            basket.receipt().unwrap().display_with(&format).to_string()
+        );
+    }
+
+    #[test]
+    fn test_render_deals_in_locale() {
+        let catalog = catalog();
+        let mut basket = Basket::new(Arc::clone(&catalog));
+
+        basket.scan_quantity("A0002", 2).unwrap();
+        basket.scan("A0001").unwrap();
+        basket.scan("A0003").unwrap();
+        basket
+            .add_deal(
+                Deal::builder("A0002")
+                    .kind(DealKind::MultiBuyFixedPrice {
+                        quantity: 2,
+                        price: eur(700),
+                    })
+                    .build()
+                    .unwrap(),
+            )
+            .unwrap();
+        basket
+            .add_group_deal(GroupDeal {
+                kind: GroupDealKind::MixAndMatch {
+                    group: ProductGroup::new("big", ["A0001", "A0003"]),
+                    quantity: 2,
+                    price: eur(1500),
+                },
+                priority: 0,
+            })
+            .unwrap();
+
+        let format = CurrencyFormat::for_locale("de-DE").unwrap();
+
+        assert_eq!(
+            "\
+A0002   Soap                  2 x 3,99 €      7,98 €
+  2 for 7,00 €                               -0,98 €
+A0001   Water                 1 x 12,99 €     12,99 €
+A0003   Towel                 1 x 5,00 €      5,00 €
+----------------------------------------------------
+Any 2 big for 15,00 €                        -2,99 €
+Subtotal                                     25,97 €
+Savings                                       3,97 €
+Total                                        22,00 €
+",
+            basket.receipt().unwrap().display_with(&format).to_string()
+        );
+    }
+
+    #[test]
+    fn test_render_taxes_per_rate() {
+        let catalog = Catalog::from_csv(
+            "sku,name,price,currency,category,unit,tax\n\
+             A0001,Water,100,EUR,,,standard\n\
+             A0002,Bread,100,EUR,,,reduced\n\
+             A0003,Book,100,EUR,,,zero\n\
+             S0001,Stamp,100,EUR,,,exempt\n",
+        )
+        .unwrap();
+        let mut basket = Basket::new(Arc::new(catalog));
+
+        basket.set_tax_policy(TaxPolicy {
+            rates: TaxRates {
+                standard: Percentage::ZERO,
+                reduced: Percentage::ZERO,
+            },
+            mode: PricingMode::Exclusive,
+        });
+        for sku in ["A0001", "A0002", "A0003", "S0001"] {
+            basket.scan(sku).unwrap();
+        }
+
+        let rendered = basket
+            .receipt()
+            .unwrap()
+            .display_with(&CurrencyFormat::default())
+            .to_string();
+
+        assert!(rendered.contains("Tax 0% on 3.00"));
+        assert!(rendered.contains("Tax exempt on 1.00"));
+        assert_eq!(1, rendered.matches("Tax 0%").count());
+    }
+
+    #[test]
+    fn test_render_taxes() {
+        let catalog = catalog();
+        let format = CurrencyFormat::for_locale("en-US").unwrap();
+        let render = |mode: PricingMode| {
+            let mut basket = Basket::new(Arc::clone(&catalog));
+
+            basket.set_tax_policy(TaxPolicy {
+                rates: TaxRates {
+                    standard: Percentage::from_percent(20).unwrap(),
+                    reduced: Percentage::from_percent(5).unwrap(),
+                },
+                mode,
+            });
+            basket.scan_quantity("A0002", 2).unwrap();
+            basket.add_deal(buy1get1free()).unwrap();
+
+            basket.receipt().unwrap().display_with(&format).to_string()
+        };
+
+        assert_eq!(
+            "\
+A0002   Soap                  2 x €3.99        €7.98
+  Buy 1 get 1 free                            -€3.99
+----------------------------------------------------
+Subtotal                                       €7.98
+Savings                                        €3.99
+Total                                          €3.99
+  incl. Tax 20% on €3.32                       €0.67
+",
+            render(PricingMode::Inclusive)
+        );
+        assert_eq!(
+            "\
+A0002   Soap                  2 x €3.99        €7.98
+  Buy 1 get 1 free                            -€3.99
+----------------------------------------------------
+Subtotal                                       €7.98
+Savings                                        €3.99
+Tax 20% on €3.99                               €0.80
+Total                                          €4.79
+",
+            render(PricingMode::Exclusive)
+        );
+    }
+}
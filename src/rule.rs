@@ -0,0 +1,834 @@
+//! A small language for promotions, parsed into rules the basket evaluates.
+//!
+//! A rule reads `when <condition> then <action>`, e.g.
+//! `when category = "drinks" and quantity >= 3 then 10% off`. Conditions test the `sku`,
+//! `category` and `quantity` of a line and the `subtotal` of the basket, and are combined with
+//! `and`, `or`, `not` and parentheses. SKUs can also be tested with `sku in ("A0001", "A0002")`.
+//! The action is written the way the deal is shown on a receipt: `12.5% off`, `0.50 off` per
+//! unit, `3 for 10.00` or `buy 2 get 1 free`. Keywords are not case sensitive.
+
+use std::{fmt::Display, iter::Peekable, str::CharIndices};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    catalog::Product,
+    deal::{DealKind, Percentage},
+    money::{CurrencyCode, Money},
+    validity::Validity,
+};
+
+/// A promotion rule: an action taken on every basket line a condition holds for.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    pub condition: Condition,
+    pub action: DealKind,
+    /// When the rule is on offer. Rules are parsed as always valid.
+    #[serde(default, skip_serializing_if = "Validity::is_always")]
+    pub validity: Validity,
+}
+
+/// A test on a basket line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// The line is for one of the SKUs.
+    Sku(Vec<String>),
+    Category(String),
+    /// Compares the quantity on the line, in pieces, grams or millilitres.
+    Quantity(Comparison, u32),
+    /// Compares the regular price of everything in the basket.
+    Subtotal(Comparison, Money),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+/// How a value on the line is compared with the value in a [`Condition`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+/// Why a rule could not be parsed.
+#[derive(Debug, PartialEq)]
+pub struct RuleError {
+    /// The character the problem was found at, counting from 1.
+    pub column: usize,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// A keyword or field name, in lower case.
+    Word(String),
+    String(String),
+    /// Digits with an optional fractional part, as written.
+    Number(String),
+    Symbol(&'static str),
+}
+
+/// How many levels deep a condition may be, counting parentheses, `not` and every `and` or
+/// `or`, so that nesting cannot exhaust the stack while parsing or evaluating a rule.
+const MAX_DEPTH: usize = 64;
+
+/// Turns tokens into a rule, by recursive descent.
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    /// How deep the condition being parsed is.
+    depth: usize,
+    /// The column just past the end of the input.
+    end: usize,
+    currency: CurrencyCode,
+}
+
+impl Rule {
+    /// Parses a rule whose amounts are in `currency`.
+    pub fn parse(source: &str, currency: CurrencyCode) -> Result<Self, RuleError> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+            depth: 0,
+            end: source.chars().count() + 1,
+            currency,
+        };
+
+        parser.expect_word("when")?;
+        let condition = parser.condition()?;
+        parser.expect_word("then")?;
+        let action = parser.action()?;
+
+        match parser.next() {
+            Some((column, _)) => Err(RuleError::new(column, "expected the end of the rule")),
+            None => Ok(Rule {
+                condition,
+                action,
+                validity: Validity::default(),
+            }),
+        }
+    }
+
+    /// Every SKU the rule names.
+    pub fn skus(&self) -> impl Iterator<Item = &String> {
+        self.condition
+            .all()
+            .into_iter()
+            .flat_map(|condition| match condition {
+                Condition::Sku(skus) => skus.as_slice(),
+                _ => &[],
+            })
+    }
+
+    /// Every amount of money the rule mentions.
+    pub fn amounts(&self) -> impl Iterator<Item = Money> + '_ {
+        self.condition
+            .all()
+            .into_iter()
+            .filter_map(|condition| match condition {
+                Condition::Subtotal(_, amount) => Some(*amount),
+                _ => None,
+            })
+            .chain(self.action.amount())
+    }
+}
+
+impl Condition {
+    /// Whether the condition holds for a line of `quantity` units of `product`, in a basket
+    /// whose regular price is `subtotal`.
+    pub fn matches(&self, product: &Product, quantity: u32, subtotal: Money) -> bool {
+        match self {
+            Condition::Sku(skus) => skus.contains(&product.sku),
+            Condition::Category(category) => product.category.as_ref() == Some(category),
+            Condition::Quantity(comparison, value) => comparison.holds(quantity, *value),
+            Condition::Subtotal(comparison, value) => {
+                comparison.holds(subtotal.amount, value.amount)
+            }
+            Condition::Not(condition) => !condition.matches(product, quantity, subtotal),
+            Condition::And(left, right) => {
+                left.matches(product, quantity, subtotal)
+                    && right.matches(product, quantity, subtotal)
+            }
+            Condition::Or(left, right) => {
+                left.matches(product, quantity, subtotal)
+                    || right.matches(product, quantity, subtotal)
+            }
+        }
+    }
+
+    /// The condition and everything nested in it.
+    fn all(&self) -> Vec<&Condition> {
+        let mut all = vec![self];
+        let mut index = 0;
+
+        while let Some(condition) = all.get(index) {
+            match condition {
+                Condition::Not(inner) => all.push(inner),
+                Condition::And(left, right) | Condition::Or(left, right) => {
+                    all.push(left);
+                    all.push(right);
+                }
+                _ => {}
+            }
+            index += 1;
+        }
+
+        all
+    }
+
+    /// How tightly the condition binds when written out: `or`, then `and`, then the rest.
+    fn precedence(&self) -> u8 {
+        match self {
+            Condition::Or(..) => 0,
+            Condition::And(..) => 1,
+            _ => 2,
+        }
+    }
+
+    /// Writes `condition`, in parentheses if it binds less tightly than `precedence`.
+    fn operand(
+        f: &mut std::fmt::Formatter<'_>,
+        condition: &Condition,
+        precedence: u8,
+    ) -> std::fmt::Result {
+        if condition.precedence() < precedence {
+            write!(f, "({condition})")
+        } else {
+            write!(f, "{condition}")
+        }
+    }
+}
+
+impl Comparison {
+    fn holds<T: Ord>(self, left: T, right: T) -> bool {
+        match self {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::LessOrEqual => left <= right,
+            Comparison::Greater => left > right,
+            Comparison::GreaterOrEqual => left >= right,
+        }
+    }
+}
+
+impl RuleError {
+    fn new(column: usize, reason: impl Into<String>) -> Self {
+        RuleError {
+            column,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<(usize, Token)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(_, token)| token)
+    }
+
+    /// The column of the next token, for errors about it.
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end, |(column, _)| *column)
+    }
+
+    fn error<T>(&self, reason: impl Into<String>) -> Result<T, RuleError> {
+        Err(RuleError::new(self.column(), reason))
+    }
+
+    /// Consumes the next token if it is `token`.
+    fn eat(&mut self, token: &Token) -> bool {
+        let matches = self.peek() == Some(token);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        self.eat(&Token::Word(word.to_string()))
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), RuleError> {
+        if self.eat_word(word) {
+            Ok(())
+        } else {
+            self.error(format!("expected '{word}'"))
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &'static str) -> Result<(), RuleError> {
+        if self.eat(&Token::Symbol(symbol)) {
+            Ok(())
+        } else {
+            self.error(format!("expected '{symbol}'"))
+        }
+    }
+
+    /// Goes one level deeper into the condition, unless that is deeper than [`MAX_DEPTH`].
+    fn descend(&mut self) -> Result<(), RuleError> {
+        if self.depth == MAX_DEPTH {
+            return self.error(format!(
+                "the condition is more than {MAX_DEPTH} levels deep"
+            ));
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, RuleError> {
+        let depth = self.depth;
+        let mut condition = self.conjunction()?;
+        while self.eat_word("or") {
+            self.descend()?;
+            condition = Condition::Or(Box::new(condition), Box::new(self.conjunction()?));
+        }
+
+        self.depth = depth;
+        Ok(condition)
+    }
+
+    fn conjunction(&mut self) -> Result<Condition, RuleError> {
+        let depth = self.depth;
+        let mut condition = self.negation()?;
+        while self.eat_word("and") {
+            self.descend()?;
+            condition = Condition::And(Box::new(condition), Box::new(self.negation()?));
+        }
+
+        self.depth = depth;
+        Ok(condition)
+    }
+
+    fn negation(&mut self) -> Result<Condition, RuleError> {
+        let depth = self.depth;
+        let condition = if self.eat_word("not") {
+            self.descend()?;
+            Condition::Not(Box::new(self.negation()?))
+        } else if self.eat(&Token::Symbol("(")) {
+            self.descend()?;
+            let condition = self.condition()?;
+            self.expect_symbol(")")?;
+            condition
+        } else {
+            return self.test();
+        };
+
+        self.depth = depth;
+        Ok(condition)
+    }
+
+    fn test(&mut self) -> Result<Condition, RuleError> {
+        let field = match self.peek() {
+            Some(Token::Word(field)) => field.clone(),
+            _ => return self.error("expected sku, category, quantity or subtotal"),
+        };
+
+        match field.as_str() {
+            "sku" => {
+                self.position += 1;
+                if self.eat_word("in") {
+                    self.expect_symbol("(")?;
+                    let mut skus = vec![self.string()?];
+                    while self.eat(&Token::Symbol(",")) {
+                        skus.push(self.string()?);
+                    }
+                    self.expect_symbol(")")?;
+
+                    return Ok(Condition::Sku(skus));
+                }
+
+                let negated = self.equality()?;
+                Ok(not_if(negated, Condition::Sku(vec![self.string()?])))
+            }
+            "category" => {
+                self.position += 1;
+                let negated = self.equality()?;
+                Ok(not_if(negated, Condition::Category(self.string()?)))
+            }
+            "quantity" => {
+                self.position += 1;
+                let comparison = self.comparison()?;
+                Ok(Condition::Quantity(comparison, self.whole_number()?))
+            }
+            "subtotal" => {
+                self.position += 1;
+                let comparison = self.comparison()?;
+                Ok(Condition::Subtotal(comparison, self.amount()?))
+            }
+            _ => self.error("expected sku, category, quantity or subtotal"),
+        }
+    }
+
+    /// Parses `=` or `!=`, returning whether it was `!=`.
+    fn equality(&mut self) -> Result<bool, RuleError> {
+        match self.comparison()? {
+            Comparison::Equal => Ok(false),
+            Comparison::NotEqual => Ok(true),
+            _ => {
+                self.position -= 1;
+                self.error("expected '=' or '!='")
+            }
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, RuleError> {
+        let comparison = match self.peek() {
+            Some(Token::Symbol("=")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            _ => return self.error("expected a comparison such as '=' or '>='"),
+        };
+        self.position += 1;
+
+        Ok(comparison)
+    }
+
+    fn string(&mut self) -> Result<String, RuleError> {
+        match self.peek() {
+            Some(Token::String(string)) => {
+                let string = string.clone();
+                self.position += 1;
+                Ok(string)
+            }
+            _ => self.error("expected a quoted string"),
+        }
+    }
+
+    /// The next number as written, along with its column.
+    fn number(&mut self) -> Result<(usize, String), RuleError> {
+        match self.tokens.get(self.position) {
+            Some((column, Token::Number(number))) => {
+                let number = (*column, number.clone());
+                self.position += 1;
+                Ok(number)
+            }
+            _ => self.error("expected a number"),
+        }
+    }
+
+    fn whole_number(&mut self) -> Result<u32, RuleError> {
+        let (column, number) = self.number()?;
+
+        number
+            .parse()
+            .map_err(|_| RuleError::new(column, format!("'{number}' is not a whole number")))
+    }
+
+    /// A whole number of at least 1.
+    fn count(&mut self) -> Result<u32, RuleError> {
+        let column = self.column();
+
+        match self.whole_number()? {
+            0 => Err(RuleError::new(column, "must be at least 1")),
+            count => Ok(count),
+        }
+    }
+
+    fn amount(&mut self) -> Result<Money, RuleError> {
+        let (column, number) = self.number()?;
+        self.money(column, &number)
+    }
+
+    fn money(&self, column: usize, number: &str) -> Result<Money, RuleError> {
+        let amount = decimal(number, self.currency.exponent()).ok_or_else(|| {
+            RuleError::new(
+                column,
+                format!("'{number}' is not an amount of {}", self.currency),
+            )
+        })?;
+
+        Ok(Money::new(amount, self.currency))
+    }
+
+    fn action(&mut self) -> Result<DealKind, RuleError> {
+        if self.eat_word("buy") {
+            let buy = self.count()?;
+            self.expect_word("get")?;
+            let free = self.count()?;
+            self.expect_word("free")?;
+
+            return Ok(DealKind::BuyNGetMFree { buy, free });
+        }
+
+        let (column, number) = self.number().or_else(|_| {
+            self.error("expected '10% off', '0.50 off', '3 for 10.00' or 'buy 2 get 1 free'")
+        })?;
+
+        if self.eat(&Token::Symbol("%")) {
+            self.expect_word("off")?;
+            let percentage = decimal(&number, 2)
+                .and_then(|basis_points| u32::try_from(basis_points).ok())
+                .and_then(|basis_points| Percentage::from_basis_points(basis_points).ok())
+                .ok_or_else(|| {
+                    RuleError::new(column, format!("'{number}%' is not a percentage"))
+                })?;
+
+            Ok(DealKind::PercentageDiscount(percentage))
+        } else if self.eat_word("for") {
+            let quantity = match number.parse() {
+                Ok(0) | Err(_) => {
+                    return Err(RuleError::new(
+                        column,
+                        "expected a whole number of at least 1",
+                    ))
+                }
+                Ok(quantity) => quantity,
+            };
+
+            Ok(DealKind::MultiBuyFixedPrice {
+                quantity,
+                price: self.amount()?,
+            })
+        } else if self.eat_word("off") {
+            Ok(DealKind::FixedAmountOff {
+                amount: self.money(column, &number)?,
+            })
+        } else {
+            self.error("expected '%', 'for' or 'off'")
+        }
+    }
+}
+
+fn not_if(negated: bool, condition: Condition) -> Condition {
+    if negated {
+        Condition::Not(Box::new(condition))
+    } else {
+        condition
+    }
+}
+
+/// Reads a decimal number with at most `exponent` fractional digits as a whole number of
+/// `10^-exponent` units, e.g. `7.5` with exponent 2 as 750.
+fn decimal(number: &str, exponent: u32) -> Option<u64> {
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if fraction.len() > exponent as usize {
+        return None;
+    }
+
+    let scale = 10u64.pow(exponent - fraction.len() as u32);
+    let digits: u64 = format!("{whole}{fraction}").parse().ok()?;
+
+    digits.checked_mul(scale)
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, RuleError> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<CharIndices> = source.char_indices().peekable();
+    let mut column = 0;
+    // Reads characters while `accept` holds for them, counting columns as it goes.
+    let take_while =
+        |chars: &mut Peekable<CharIndices>, column: &mut usize, accept: fn(char) -> bool| {
+            let mut taken = String::new();
+            while let Some((_, c)) = chars.peek().copied().filter(|(_, c)| accept(*c)) {
+                taken.push(c);
+                chars.next();
+                *column += 1;
+            }
+            taken
+        };
+
+    while let Some(&(_, c)) = chars.peek() {
+        let start = column + 1;
+
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                column += 1;
+                continue;
+            }
+            c if c.is_ascii_alphabetic() => Token::Word(
+                take_while(&mut chars, &mut column, |c| {
+                    c.is_ascii_alphanumeric() || c == '_'
+                })
+                .to_ascii_lowercase(),
+            ),
+            c if c.is_ascii_digit() => {
+                let number =
+                    take_while(&mut chars, &mut column, |c| c.is_ascii_digit() || c == '.');
+                if number.matches('.').count() > 1 || number.ends_with('.') {
+                    return Err(RuleError::new(start, format!("'{number}' is not a number")));
+                }
+                Token::Number(number)
+            }
+            '"' => {
+                chars.next();
+                column += 1;
+                let string = take_while(&mut chars, &mut column, |c| c != '"');
+                if chars.next().is_none() {
+                    return Err(RuleError::new(start, "unterminated string"));
+                }
+                column += 1;
+                Token::String(string)
+            }
+            _ => {
+                chars.next();
+                column += 1;
+                let symbol = match (c, chars.peek().map(|(_, next)| *next)) {
+                    ('!', Some('=')) => "!=",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('=', _) => "=",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    ('(', _) => "(",
+                    (')', _) => ")",
+                    (',', _) => ",",
+                    ('%', _) => "%",
+                    _ => return Err(RuleError::new(start, format!("unexpected '{c}'"))),
+                };
+                if symbol.len() == 2 {
+                    chars.next();
+                    column += 1;
+                }
+                Token::Symbol(symbol)
+            }
+        };
+
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+impl Display for Rule {
+    /// Writes the rule in the language it is parsed from.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "when {} then {}", self.condition, self.action)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::Sku(skus) if skus.len() == 1 => write!(f, "sku = \"{}\"", skus[0]),
+            Condition::Sku(skus) => {
+                let skus: Vec<String> = skus.iter().map(|sku| format!("\"{sku}\"")).collect();
+                write!(f, "sku in ({})", skus.join(", "))
+            }
+            Condition::Category(category) => write!(f, "category = \"{category}\""),
+            Condition::Quantity(comparison, value) => write!(f, "quantity {comparison} {value}"),
+            Condition::Subtotal(comparison, value) => write!(f, "subtotal {comparison} {value}"),
+            Condition::Not(condition) => {
+                f.write_str("not ")?;
+                Condition::operand(f, condition, 2)
+            }
+            Condition::And(left, right) => {
+                Condition::operand(f, left, 1)?;
+                f.write_str(" and ")?;
+                Condition::operand(f, right, 2)
+            }
+            Condition::Or(left, right) => {
+                Condition::operand(f, left, 0)?;
+                f.write_str(" or ")?;
+                Condition::operand(f, right, 1)
+            }
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Comparison::Equal => "=",
+            Comparison::NotEqual => "!=",
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+        })
+    }
+}
+
+impl Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "column {}: {}", self.column, self.reason)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        catalog::{Product, Unit},
+        deal::{DealKind, Percentage},
+        money::CurrencyCode,
+        rule::{Comparison, Condition, Rule},
+        tax::TaxClass,
+        test_support::eur,
+        validity::Validity,
+    };
+
+    fn parse(source: &str) -> Rule {
+        Rule::parse(source, CurrencyCode::Eur).unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        Rule::parse(source, CurrencyCode::Eur)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Rule {
+                condition: Condition::Or(
+                    Box::new(Condition::And(
+                        Box::new(Condition::Category("drinks".to_string())),
+                        Box::new(Condition::Quantity(Comparison::GreaterOrEqual, 3)),
+                    )),
+                    Box::new(Condition::Not(Box::new(Condition::Sku(vec![
+                        "A0001".to_string(),
+                        "A0002".to_string(),
+                    ])))),
+                ),
+                action: DealKind::PercentageDiscount(Percentage::from_basis_points(1250).unwrap()),
+                validity: Validity::default(),
+            },
+            parse(
+                r#"WHEN category = "drinks" and quantity >= 3
+                   or not sku in ("A0001", "A0002") then 12.5% off"#
+            )
+        );
+        assert_eq!(
+            DealKind::MultiBuyFixedPrice {
+                quantity: 3,
+                price: eur(1000),
+            },
+            parse(r#"when subtotal > 20 then 3 for 10.00"#).action
+        );
+        assert_eq!(
+            DealKind::FixedAmountOff { amount: eur(50) },
+            parse(r#"when sku != "A0001" then 0.5 off"#).action
+        );
+        assert_eq!(
+            DealKind::BuyNGetMFree { buy: 2, free: 1 },
+            parse(r#"when quantity >= 3 then Buy 2 get 1 free"#).action
+        );
+    }
+
+    #[test]
+    fn test_display_round_trips() {
+        for source in [
+            r#"when (sku = "A0001" or sku = "A0002") and not quantity < 2 then 10% off"#,
+            r#"when sku in ("A0001", "A0002") or category = "drinks" and subtotal >= 20.00 then 2 for 7.00"#,
+            r#"when not (category = "drinks" or quantity = 1) then Buy 2 get 1 free"#,
+        ] {
+            let rule = parse(source);
+
+            assert_eq!(rule, parse(&rule.to_string()));
+        }
+        assert_eq!(
+            r#"when not sku = "A0001" and quantity >= 2 then 1.00 off"#,
+            parse(r#"when sku != "A0001" and quantity >= 2 then 1 off"#).to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            "column 1: expected 'when'",
+            error("if quantity > 1 then 10% off")
+        );
+        assert_eq!(
+            "column 6: expected sku, category, quantity or subtotal",
+            error("when price > 1 then 10% off")
+        );
+        assert_eq!(
+            "column 25: '10.005' is not an amount of EUR",
+            error("when subtotal >= 1 then 10.005 off")
+        );
+        assert_eq!(
+            "column 24: '120%' is not a percentage",
+            error("when quantity > 1 then 120% off")
+        );
+        assert_eq!(
+            "column 34: must be at least 1",
+            error("when quantity > 1 then buy 2 get 0 free")
+        );
+        assert_eq!(
+            "column 12: unterminated string",
+            error(r#"when sku = "A0001 then 10% off"#)
+        );
+        assert_eq!(
+            "column 32: expected the end of the rule",
+            error("when quantity > 1 then 10% off and more")
+        );
+        assert_eq!(
+            "column 26: expected '%', 'for' or 'off'",
+            error("when quantity > 1 then 10")
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| {
+            format!(
+                "when {}quantity > 1{} then 10% off",
+                "(".repeat(depth),
+                ")".repeat(depth)
+            )
+        };
+
+        parse(&nested(64));
+        assert_eq!(
+            "column 71: the condition is more than 64 levels deep",
+            error(&nested(65))
+        );
+        assert!(error(&nested(200_000)).contains("more than 64 levels deep"));
+        assert!(error(&format!(
+            "when {}quantity > 1 then 10% off",
+            "not ".repeat(200_000)
+        ))
+        .contains("more than 64 levels deep"));
+        assert!(error(&format!(
+            "when quantity > 1{} then 10% off",
+            " and quantity > 1".repeat(200_000)
+        ))
+        .contains("more than 64 levels deep"));
+    }
+
+    #[test]
+    fn test_matches() {
+        let water = Product::new(
+            "A0001".to_string(),
+            "Water".to_string(),
+            eur(1299),
+            Some("drinks".to_string()),
+            Unit::Piece,
+            TaxClass::Standard,
+        );
+        let rule = parse(
+            r#"when category = "drinks" and (quantity >= 3 or subtotal >= 50.00) then 10% off"#,
+        );
+
+        assert!(!rule.condition.matches(&water, 2, eur(4999)));
+        assert!(rule.condition.matches(&water, 3, eur(4999)));
+        assert!(rule.condition.matches(&water, 1, eur(5000)));
+        assert_eq!(
+            vec!["A0001"],
+            parse(r#"when sku = "A0001" then 10% off"#)
+                .skus()
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,447 @@
+//! Value added tax: the classes products are taxed in and the tax on a basket per rate.
+
+use std::{fmt::Display, fs, path::Path, str::FromStr};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    basket::BasketError,
+    deal::Percentage,
+    money::{Money, MoneyError, RoundingMode},
+};
+
+/// The VAT class a product is taxed in.
+#[derive(
+    Debug, Default, Clone, Copy, Hash, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum TaxClass {
+    #[default]
+    Standard,
+    Reduced,
+    /// Taxed at 0%, but still listed on invoices as a taxable supply.
+    Zero,
+    /// Outside the scope of VAT altogether.
+    Exempt,
+}
+
+/// Whether catalog prices, and the amounts in deals, include tax.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PricingMode {
+    /// Prices are what the customer pays; the tax is extracted from them.
+    #[default]
+    Inclusive,
+    /// Prices are net; the tax is added on top of the discounted total.
+    Exclusive,
+}
+
+/// The rates charged for the taxed classes.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TaxRates {
+    pub standard: Percentage,
+    pub reduced: Percentage,
+}
+
+/// How a basket is taxed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TaxPolicy {
+    pub rates: TaxRates,
+    pub mode: PricingMode,
+}
+
+/// The tax rules of a country, state or other region, with the rates in force over time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaxJurisdiction {
+    pub name: String,
+    /// What the tax is called on receipts, e.g. `VAT` or `Sales tax`.
+    #[serde(default = "default_label")]
+    pub label: String,
+    pub pricing: PricingMode,
+    /// The rates for the standard and reduced classes. Where the periods of several rates for
+    /// a class overlap, the one that started last applies, so temporary changes can be listed
+    /// alongside the rate they interrupt.
+    pub rates: Vec<TaxRate>,
+}
+
+/// The rate of one tax class from `from` until `until`, both inclusive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaxRate {
+    pub class: TaxClass,
+    pub rate: Percentage,
+    pub from: NaiveDate,
+    /// The last day the rate applies, if its end is known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<NaiveDate>,
+}
+
+/// Why a tax jurisdiction could not be loaded.
+#[derive(Debug)]
+pub enum JurisdictionError {
+    Io(std::io::Error),
+    Invalid(String),
+}
+
+/// The tax on everything a basket charges at one rate.
+#[derive(Debug, PartialEq)]
+pub struct TaxSummary {
+    /// The rate charged, or `None` for exempt goods, which are summarized on their own even
+    /// though they are not taxed either.
+    pub rate: Option<Percentage>,
+    /// The taxable amount, after discounts and without tax.
+    pub net: Money,
+    pub tax: Money,
+    pub gross: Money,
+}
+
+impl TaxRates {
+    /// The rate for `class`, or `None` if it is exempt.
+    pub fn rate(&self, class: TaxClass) -> Option<Percentage> {
+        match class {
+            TaxClass::Standard => Some(self.standard),
+            TaxClass::Reduced => Some(self.reduced),
+            TaxClass::Zero => Some(Percentage::ZERO),
+            TaxClass::Exempt => None,
+        }
+    }
+}
+
+impl TaxPolicy {
+    /// Works out the tax on `amount`, charged at `rate` after every discount, or not at all if
+    /// the goods are exempt.
+    ///
+    /// The tax is rounded half up once per rate rather than per line, as on an invoice.
+    pub fn summarize(
+        &self,
+        rate: Option<Percentage>,
+        amount: Money,
+    ) -> Result<TaxSummary, MoneyError> {
+        let basis_points = rate.map_or(0, u32::from);
+        // Tax-inclusive prices are `100% + rate` of the net amount.
+        let denominator = match self.mode {
+            PricingMode::Inclusive => 10_000 + basis_points,
+            PricingMode::Exclusive => 10_000,
+        };
+        let tax =
+            amount.checked_mul_div_rounded(basis_points, denominator, RoundingMode::HalfUp)?;
+        let (net, gross) = match self.mode {
+            PricingMode::Inclusive => (amount.saturating_sub(tax)?, amount),
+            PricingMode::Exclusive => (amount, amount.checked_add(tax)?),
+        };
+
+        Ok(TaxSummary {
+            rate,
+            net,
+            tax,
+            gross,
+        })
+    }
+}
+
+impl TaxJurisdiction {
+    /// Loads a jurisdiction from a JSON file, see [`TaxJurisdiction::from_json`].
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, JurisdictionError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a `{ "name", "pricing", "rates" }` object, where each rate has a `"class"`, a
+    /// `"rate"` in basis points, a `"from"` date and an optional `"until"` date, e.g.
+    /// `{ "class": "standard", "rate": 1900, "from": "2007-01-01" }`. An optional `"label"`
+    /// names the tax on receipts, `Tax` by default.
+    pub fn from_json(input: &str) -> Result<Self, JurisdictionError> {
+        let jurisdiction: TaxJurisdiction = serde_json::from_str(input).map_err(|error| {
+            JurisdictionError::Invalid(format!("line {}: {error}", error.line()))
+        })?;
+
+        if jurisdiction.label.trim().is_empty() {
+            return Err(JurisdictionError::Invalid("the label is empty".to_string()));
+        }
+        for (index, rate) in jurisdiction.rates.iter().enumerate() {
+            let invalid = |reason: String| {
+                JurisdictionError::Invalid(format!("rate {}: {reason}", index + 1))
+            };
+
+            if jurisdiction.rates[..index]
+                .iter()
+                .any(|other| other.class == rate.class && other.from == rate.from)
+            {
+                return Err(invalid(format!(
+                    "another {} rate also starts on {}",
+                    rate.class, rate.from
+                )));
+            }
+            if let TaxClass::Zero | TaxClass::Exempt = rate.class {
+                return Err(invalid(format!(
+                    "the {} class has no rate to set",
+                    rate.class
+                )));
+            }
+            if let Some(until) = rate.until.filter(|&until| until < rate.from) {
+                return Err(invalid(format!(
+                    "ends on {until}, before it starts on {}",
+                    rate.from
+                )));
+            }
+        }
+
+        Ok(jurisdiction)
+    }
+
+    /// The rates and pricing mode in force on `date`.
+    pub fn policy_on(&self, date: NaiveDate) -> Result<TaxPolicy, BasketError> {
+        let rate = |class: TaxClass| {
+            self.rates
+                .iter()
+                .filter(|rate| {
+                    rate.class == class
+                        && rate.from <= date
+                        && rate.until.is_none_or(|until| date <= until)
+                })
+                .max_by_key(|rate| rate.from)
+                .map(|rate| rate.rate)
+                .ok_or_else(|| BasketError::MissingTaxRate {
+                    jurisdiction: self.name.clone(),
+                    class,
+                    date,
+                })
+        };
+
+        Ok(TaxPolicy {
+            rates: TaxRates {
+                standard: rate(TaxClass::Standard)?,
+                reduced: rate(TaxClass::Reduced)?,
+            },
+            mode: self.pricing,
+        })
+    }
+}
+
+/// What a tax is called on receipts when nothing more specific is known.
+pub(crate) fn default_label() -> String {
+    "Tax".to_string()
+}
+
+impl Display for JurisdictionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JurisdictionError::Io(error) => write!(f, "could not read tax jurisdiction: {error}"),
+            JurisdictionError::Invalid(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for JurisdictionError {}
+
+impl From<std::io::Error> for JurisdictionError {
+    fn from(error: std::io::Error) -> Self {
+        JurisdictionError::Io(error)
+    }
+}
+
+impl Display for TaxClass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            TaxClass::Standard => "standard",
+            TaxClass::Reduced => "reduced",
+            TaxClass::Zero => "zero",
+            TaxClass::Exempt => "exempt",
+        })
+    }
+}
+
+impl FromStr for TaxClass {
+    type Err = String;
+
+    fn from_str(class: &str) -> Result<Self, Self::Err> {
+        match class {
+            "standard" => Ok(TaxClass::Standard),
+            "reduced" => Ok(TaxClass::Reduced),
+            "zero" => Ok(TaxClass::Zero),
+            "exempt" => Ok(TaxClass::Exempt),
+            _ => Err(format!(
+                "unknown tax class '{class}', expected standard, reduced, zero or exempt"
+            )),
+        }
+    }
+}
+
+impl FromStr for PricingMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "inclusive" => Ok(PricingMode::Inclusive),
+            "exclusive" => Ok(PricingMode::Exclusive),
+            _ => Err(format!(
+                "unknown pricing mode '{mode}', expected inclusive or exclusive"
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::{
+        basket::BasketError,
+        deal::Percentage,
+        tax::{PricingMode, TaxClass, TaxJurisdiction, TaxPolicy, TaxRates, TaxSummary},
+        test_support::eur,
+    };
+
+    fn policy(mode: PricingMode) -> TaxPolicy {
+        TaxPolicy {
+            rates: TaxRates {
+                standard: Percentage::from_percent(20).unwrap(),
+                reduced: Percentage::from_percent(5).unwrap(),
+            },
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_inclusive() {
+        assert_eq!(
+            Ok(TaxSummary {
+                rate: Some(Percentage::from_percent(20).unwrap()),
+                net: eur(1415),
+                tax: eur(283),
+                gross: eur(1698),
+            }),
+            policy(PricingMode::Inclusive)
+                .summarize(Some(Percentage::from_percent(20).unwrap()), eur(1698))
+        );
+    }
+
+    #[test]
+    fn test_exclusive() {
+        assert_eq!(
+            Ok(TaxSummary {
+                rate: Some(Percentage::from_percent(5).unwrap()),
+                net: eur(1299),
+                tax: eur(65),
+                gross: eur(1364),
+            }),
+            policy(PricingMode::Exclusive)
+                .summarize(Some(Percentage::from_percent(5).unwrap()), eur(1299))
+        );
+    }
+
+    #[test]
+    fn test_untaxed_classes() {
+        for mode in [PricingMode::Inclusive, PricingMode::Exclusive] {
+            for rate in [Some(Percentage::ZERO), None] {
+                let summary = policy(mode).summarize(rate, eur(500)).unwrap();
+
+                assert_eq!(eur(0), summary.tax);
+                assert_eq!(eur(500), summary.net);
+                assert_eq!(eur(500), summary.gross);
+            }
+        }
+    }
+
+    fn germany() -> TaxJurisdiction {
+        TaxJurisdiction::from_json(
+            r#"{
+                "name": "Germany",
+                "pricing": "inclusive",
+                "rates": [
+                    { "class": "standard", "rate": 1900, "from": "2007-01-01" },
+                    { "class": "standard", "rate": 1600, "from": "2020-07-01", "until": "2020-12-31" },
+                    { "class": "reduced", "rate": 700, "from": "1983-07-01" },
+                    { "class": "reduced", "rate": 500, "from": "2020-07-01", "until": "2020-12-31" }
+                ]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn test_policy_on() {
+        let germany = germany();
+        let standard = |day| germany.policy_on(date(day)).unwrap().rates.standard;
+
+        assert_eq!(
+            Percentage::from_percent(19).unwrap(),
+            standard("2020-06-30")
+        );
+        assert_eq!(
+            Percentage::from_percent(16).unwrap(),
+            standard("2020-07-01")
+        );
+        assert_eq!(
+            Percentage::from_percent(16).unwrap(),
+            standard("2020-12-31")
+        );
+        assert_eq!(
+            Percentage::from_percent(19).unwrap(),
+            standard("2021-01-01")
+        );
+        assert_eq!(
+            PricingMode::Inclusive,
+            germany.policy_on(date("2021-01-01")).unwrap().mode
+        );
+        assert_eq!(
+            Err(BasketError::MissingTaxRate {
+                jurisdiction: "Germany".to_string(),
+                class: TaxClass::Standard,
+                date: date("2000-01-01"),
+            }),
+            germany.policy_on(date("2000-01-01"))
+        );
+    }
+
+    #[test]
+    fn test_invalid_jurisdictions() {
+        let error = |rates: &str| {
+            TaxJurisdiction::from_json(&format!(
+                r#"{{ "name": "Test", "pricing": "exclusive", "rates": [{rates}] }}"#
+            ))
+            .unwrap_err()
+            .to_string()
+        };
+
+        assert_eq!(
+            "rate 2: another standard rate also starts on 2020-01-01",
+            error(
+                r#"{ "class": "standard", "rate": 1900, "from": "2020-01-01" },
+                   { "class": "standard", "rate": 1600, "from": "2020-01-01" }"#
+            )
+        );
+        assert_eq!(
+            "rate 1: the exempt class has no rate to set",
+            error(r#"{ "class": "exempt", "rate": 0, "from": "2020-01-01" }"#)
+        );
+        assert_eq!(
+            "rate 1: ends on 2019-12-31, before it starts on 2020-01-01",
+            error(
+                r#"{ "class": "reduced", "rate": 700, "from": "2020-01-01", "until": "2019-12-31" }"#
+            )
+        );
+        assert!(
+            error(r#"{ "class": "reduced", "rate": 10001, "from": "2020-01-01" }"#)
+                .starts_with("line 1: ")
+        );
+        assert_eq!(
+            "the label is empty",
+            TaxJurisdiction::from_json(
+                r#"{ "name": "Test", "label": " ", "pricing": "exclusive", "rates": [] }"#
+            )
+            .unwrap_err()
+            .to_string()
+        );
+        assert_eq!("Tax", germany().label);
+    }
+
+    #[test]
+    fn test_parse_tax_class() {
+        assert_eq!(Ok(TaxClass::Zero), "zero".parse());
+        assert!("luxury".parse::<TaxClass>().is_err());
+    }
+}
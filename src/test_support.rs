@@ -0,0 +1,34 @@
+//! Fixtures shared by the unit tests.
+
+use std::sync::Arc;
+
+use crate::{
+    catalog::Catalog,
+    deal::{Deal, DealKind},
+    money::{CurrencyCode, Money},
+};
+
+/// Water at 12.99, soap at 3.99 and a towel at 5.00, all in euros.
+pub(crate) fn catalog() -> Arc<Catalog> {
+    Arc::new(
+        Catalog::from_csv(
+            "sku,name,price,currency\n\
+             A0001,Water,1299,EUR\n\
+             A0002,Soap,399,EUR\n\
+             A0003,Towel,500,EUR\n",
+        )
+        .unwrap(),
+    )
+}
+
+/// Buy one soap, get one free.
+pub(crate) fn buy1get1free() -> Deal {
+    Deal::builder("A0002")
+        .kind(DealKind::Buy1Get1Free)
+        .build()
+        .unwrap()
+}
+
+pub(crate) fn eur(amount: u64) -> Money {
+    Money::new(amount, CurrencyCode::Eur)
+}
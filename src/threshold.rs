@@ -0,0 +1,196 @@
+//! Deals on the basket as a whole, for spending above a threshold.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    basket::BasketError,
+    deal::Percentage,
+    money::{CurrencyFormat, Money, RoundingMode},
+};
+
+/// A basket-level deal that rewards spending at least `threshold`, e.g. "5.00 off orders of
+/// 40.00 or more".
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdDeal {
+    pub threshold: Money,
+    pub base: ThresholdBase,
+    pub reward: ThresholdReward,
+}
+
+/// Which amount is compared against the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdBase {
+    /// The regular price of all items, ignoring product and group deals.
+    BeforeDiscounts,
+    /// What the customer pays after product and group deals.
+    AfterDiscounts,
+}
+
+/// What the customer gets for reaching the threshold of a [`ThresholdDeal`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThresholdReward {
+    /// Takes a percentage off the discounted total, rounding the total down.
+    PercentageOff(Percentage),
+    /// Takes a fixed amount off the discounted total, never going below zero.
+    AmountOff(Money),
+}
+
+impl ThresholdDeal {
+    /// Returns what the customer pays once the deal is applied to `discounted`, or `None` if
+    /// the threshold isn't reached.
+    ///
+    /// `gross` is the regular price of the basket, and the threshold counts as reached when the
+    /// amount selected by `base` is at least `threshold`.
+    pub fn apply(&self, gross: Money, discounted: Money) -> Result<Option<Money>, BasketError> {
+        let spent = match self.base {
+            ThresholdBase::BeforeDiscounts => gross,
+            ThresholdBase::AfterDiscounts => discounted,
+        };
+
+        if spent.currency != self.threshold.currency {
+            return Err(BasketError::CurrencyMismatch {
+                expected: spent.currency,
+                found: self.threshold.currency,
+            });
+        }
+
+        if spent.amount < self.threshold.amount {
+            return Ok(None);
+        }
+
+        let total = match self.reward {
+            ThresholdReward::PercentageOff(percentage) => {
+                percentage.take_off(discounted, RoundingMode::Floor)?
+            }
+            ThresholdReward::AmountOff(amount) => discounted.saturating_sub(amount)?,
+        };
+
+        Ok(Some(total))
+    }
+
+    /// Describes the deal as it is shown on a receipt, formatting amounts according to
+    /// `format`.
+    pub fn describe(&self, format: &CurrencyFormat) -> String {
+        let reward = match &self.reward {
+            ThresholdReward::PercentageOff(percentage) => percentage.to_string(),
+            ThresholdReward::AmountOff(amount) => amount.display_with(format).to_string(),
+        };
+
+        format!(
+            "{reward} off orders of {} or more",
+            self.threshold.display_with(format)
+        )
+    }
+}
+
+impl Display for ThresholdDeal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.describe(&CurrencyFormat::default()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        basket::{Basket, BasketError},
+        deal::Percentage,
+        money::{CurrencyCode, Money},
+        test_support::{buy1get1free, catalog, eur},
+        threshold::{ThresholdBase, ThresholdDeal, ThresholdReward},
+    };
+
+    fn total(deals: &[ThresholdDeal]) -> Money {
+        let mut basket = Basket::new(catalog());
+
+        // 20.97 before and 16.98 after Buy1Get1Free.
+        basket.scan("A0001").unwrap();
+        basket.scan_quantity("A0002", 2).unwrap();
+        basket.add_deal(buy1get1free()).unwrap();
+        for deal in deals {
+            basket.add_threshold_deal(deal.clone()).unwrap();
+        }
+
+        basket.total().unwrap()
+    }
+
+    #[test]
+    fn test_threshold_before_discounts() {
+        let deal = ThresholdDeal {
+            threshold: eur(2000),
+            base: ThresholdBase::BeforeDiscounts,
+            reward: ThresholdReward::PercentageOff(Percentage::from_percent(10).unwrap()),
+        };
+
+        assert_eq!(eur(1528), total(&[deal]));
+    }
+
+    #[test]
+    fn test_threshold_after_discounts() {
+        let deal = ThresholdDeal {
+            threshold: eur(2000),
+            base: ThresholdBase::AfterDiscounts,
+            reward: ThresholdReward::PercentageOff(Percentage::from_percent(10).unwrap()),
+        };
+
+        assert_eq!(eur(1698), total(&[deal]));
+    }
+
+    #[test]
+    fn test_threshold_is_inclusive() {
+        let deal = ThresholdDeal {
+            threshold: eur(1698),
+            base: ThresholdBase::AfterDiscounts,
+            reward: ThresholdReward::AmountOff(eur(500)),
+        };
+
+        assert_eq!(eur(1198), total(&[deal]));
+    }
+
+    #[test]
+    fn test_best_threshold_deal_wins() {
+        let deals = [
+            ThresholdDeal {
+                threshold: eur(1000),
+                base: ThresholdBase::AfterDiscounts,
+                reward: ThresholdReward::AmountOff(eur(100)),
+            },
+            ThresholdDeal {
+                threshold: eur(1500),
+                base: ThresholdBase::AfterDiscounts,
+                reward: ThresholdReward::AmountOff(eur(300)),
+            },
+            ThresholdDeal {
+                threshold: eur(2000),
+                base: ThresholdBase::AfterDiscounts,
+                reward: ThresholdReward::AmountOff(eur(1000)),
+            },
+        ];
+
+        assert_eq!(eur(1398), total(&deals));
+    }
+
+    #[test]
+    fn test_amount_off_in_other_currency() {
+        let mut basket = Basket::new(catalog());
+        let deal = ThresholdDeal {
+            threshold: eur(1000),
+            base: ThresholdBase::AfterDiscounts,
+            reward: ThresholdReward::AmountOff(Money::new(100, CurrencyCode::Usd)),
+        };
+
+        basket.scan("A0001").unwrap();
+
+        assert_eq!(
+            Err(BasketError::CurrencyMismatch {
+                expected: CurrencyCode::Eur,
+                found: CurrencyCode::Usd,
+            }),
+            basket.add_threshold_deal(deal)
+        );
+        assert_eq!(Ok(eur(1299)), basket.total());
+    }
+}
@@ -0,0 +1,150 @@
+//! When deals are on offer: fixed periods and recurring windows such as happy hours.
+
+use chrono::{Datelike, NaiveDateTime, NaiveTime, Weekday};
+use serde::{Deserialize, Serialize};
+
+/// When a deal applies, in the local time of the store. The default is always.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Validity {
+    /// The moment the deal starts applying, if it has not always applied.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start: Option<NaiveDateTime>,
+    /// The moment the deal stops applying, if it ever does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<NaiveDateTime>,
+    /// Limits the deal to a window on certain days between `start` and `end`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurrence: Option<Recurrence>,
+}
+
+/// A window that recurs every day, or every week on the listed days.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recurrence {
+    /// The days the window opens on; every day if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub weekdays: Vec<Weekday>,
+    pub from: NaiveTime,
+    /// The time the window closes. A time before `from` closes it the next day, so a window
+    /// opened on Friday evening lasts into Saturday morning.
+    pub until: NaiveTime,
+}
+
+impl Validity {
+    /// Whether the deal never stops applying.
+    pub fn is_always(&self) -> bool {
+        *self == Validity::default()
+    }
+
+    /// Whether the deal applies at `at`.
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        self.start.is_none_or(|start| start <= at)
+            && self.end.is_none_or(|end| at < end)
+            && self
+                .recurrence
+                .as_ref()
+                .is_none_or(|recurrence| recurrence.contains(at))
+    }
+
+    /// Whether the deal can apply at all.
+    pub fn is_empty(&self) -> bool {
+        let never_starts =
+            matches!((self.start, self.end), (Some(start), Some(end)) if start >= end);
+        let never_opens = self
+            .recurrence
+            .as_ref()
+            .is_some_and(|recurrence| recurrence.from == recurrence.until);
+
+        never_starts || never_opens
+    }
+}
+
+impl Recurrence {
+    /// Whether the window is open at `at`.
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let time = at.time();
+        let opened_on = if self.from < self.until {
+            (self.from <= time && time < self.until).then(|| at.weekday())
+        } else if self.from <= time {
+            Some(at.weekday())
+        } else {
+            (time < self.until).then(|| at.weekday().pred())
+        };
+
+        opened_on.is_some_and(|day| self.weekdays.is_empty() || self.weekdays.contains(&day))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDateTime, NaiveTime, Weekday};
+
+    use crate::validity::{Recurrence, Validity};
+
+    fn at(at: &str) -> NaiveDateTime {
+        at.parse().unwrap()
+    }
+
+    fn time(time: &str) -> NaiveTime {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_period() {
+        let validity = Validity {
+            start: Some(at("2024-03-04T00:00:00")),
+            end: Some(at("2024-03-11T00:00:00")),
+            recurrence: None,
+        };
+
+        assert!(!validity.contains(at("2024-03-03T23:59:59")));
+        assert!(validity.contains(at("2024-03-04T00:00:00")));
+        assert!(validity.contains(at("2024-03-10T23:59:59")));
+        assert!(!validity.contains(at("2024-03-11T00:00:00")));
+        assert!(Validity::default().contains(at("1970-01-01T00:00:00")));
+    }
+
+    #[test]
+    fn test_happy_hour() {
+        let validity = Validity {
+            recurrence: Some(Recurrence {
+                weekdays: vec![Weekday::Fri],
+                from: time("17:00:00"),
+                until: time("19:00:00"),
+            }),
+            ..Validity::default()
+        };
+
+        // 2024-03-08 is a Friday.
+        assert!(!validity.contains(at("2024-03-08T16:59:59")));
+        assert!(validity.contains(at("2024-03-08T17:00:00")));
+        assert!(!validity.contains(at("2024-03-08T19:00:00")));
+        assert!(!validity.contains(at("2024-03-09T18:00:00")));
+    }
+
+    #[test]
+    fn test_window_past_midnight() {
+        let recurrence = Recurrence {
+            weekdays: vec![Weekday::Fri],
+            from: time("22:00:00"),
+            until: time("02:00:00"),
+        };
+
+        assert!(!recurrence.contains(at("2024-03-08T01:00:00")));
+        assert!(recurrence.contains(at("2024-03-08T23:00:00")));
+        assert!(recurrence.contains(at("2024-03-09T01:59:59")));
+        assert!(!recurrence.contains(at("2024-03-09T02:00:00")));
+        assert!(!recurrence.contains(at("2024-03-09T23:00:00")));
+    }
+
+    #[test]
+    fn test_empty() {
+        let validity = Validity {
+            start: Some(at("2024-03-11T00:00:00")),
+            end: Some(at("2024-03-04T00:00:00")),
+            recurrence: None,
+        };
+
+        assert!(validity.is_empty());
+        assert!(!Validity::default().is_empty());
+    }
+}
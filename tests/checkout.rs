@@ -0,0 +1,308 @@
+use std::sync::Arc;
+
+use bitside_coding_challenge::{
+    catalog::ProductGroup,
+    clock::FixedClock,
+    deal::{DealKind, Percentage, Stacking},
+    event::Event,
+    exchange::ExchangeRates,
+    group::{GroupDeal, GroupDealKind},
+    money::CurrencyFormat,
+    promotion::Promotion,
+    tax::{PricingMode, TaxJurisdiction, TaxPolicy, TaxRates},
+    threshold::{ThresholdBase, ThresholdDeal, ThresholdReward},
+    Basket, BasketError, Catalog, CurrencyCode, Deal, Money,
+};
+
+fn catalog() -> Arc<Catalog> {
+    Arc::new(
+        Catalog::from_csv(
+            "sku,name,price,currency,category,unit\n\
+             A0001,Water,1299,EUR,drinks,\n\
+             A0002,Soap,399,EUR,,\n\
+             A0003,Juice,250,EUR,drinks,\n\
+             B0001,Bananas,199,EUR,,kg\n",
+        )
+        .unwrap(),
+    )
+}
+
+fn eur(amount: u64) -> Money {
+    Money::new(amount, CurrencyCode::Eur)
+}
+
+fn deal(product: &str, kind: DealKind) -> Deal {
+    Deal::builder(product).kind(kind).build().unwrap()
+}
+
+#[test]
+fn test_product_group_and_threshold_deals() {
+    let mut basket = Basket::new(catalog());
+
+    basket.scan("A0001").unwrap();
+    basket.scan_quantity("A0002", 3).unwrap();
+    basket.scan_quantity("A0003", 2).unwrap();
+    basket.scan_weighted("B0001", 1500).unwrap();
+
+    basket
+        .add_deal(deal("A0002", DealKind::BuyNGetMFree { buy: 2, free: 1 }))
+        .unwrap();
+    basket
+        .add_group_deal(GroupDeal {
+            kind: GroupDealKind::BuyGetFree {
+                buy: ProductGroup::new("water", ["A0001"]),
+                get: ProductGroup::new("juice", ["A0003"]),
+            },
+            priority: 0,
+        })
+        .unwrap();
+    basket
+        .add_threshold_deal(ThresholdDeal {
+            threshold: eur(2000),
+            base: ThresholdBase::AfterDiscounts,
+            reward: ThresholdReward::AmountOff(eur(100)),
+        })
+        .unwrap();
+
+    let receipt = basket.receipt().unwrap();
+
+    // 12.99 + 3 x 3.99 + 2 x 2.50 + 1.5 kg x 1.99/kg, rounded half up.
+    assert_eq!(eur(3295), receipt.subtotal);
+    // One soap and one juice free, then 1.00 off for spending over 20.00.
+    assert_eq!(eur(749), receipt.savings);
+    assert_eq!(eur(2546), receipt.total);
+    assert_eq!(receipt.total, basket.total().unwrap());
+}
+
+#[test]
+fn test_stacked_deals_on_the_receipt() {
+    let mut basket = Basket::new(catalog());
+    let percentage = Deal::builder("A0001")
+        .kind(DealKind::PercentageDiscount(
+            Percentage::from_percent(10).unwrap(),
+        ))
+        .stacking(Stacking::Stackable)
+        .build()
+        .unwrap();
+    let amount = Deal::builder("A0001")
+        .kind(DealKind::FixedAmountOff { amount: eur(100) })
+        .stacking(Stacking::Stackable)
+        .priority(1)
+        .build()
+        .unwrap();
+
+    basket.scan("A0001").unwrap();
+    basket.add_deal(percentage).unwrap();
+    basket.add_deal(amount).unwrap();
+
+    let format = CurrencyFormat::for_locale("en-US").unwrap();
+
+    assert_eq!(
+        "\
+A0001   Water                 1 x €12.99      €12.99
+  10% off + €1.00 off                         -€2.30
+----------------------------------------------------
+Subtotal                                      €12.99
+Savings                                        €2.30
+Total                                         €10.69
+",
+        basket.receipt().unwrap().display_with(&format).to_string()
+    );
+}
+
+#[test]
+fn test_invalid_input_is_rejected() {
+    let mut basket = Basket::new(catalog());
+
+    assert_eq!(
+        Err(BasketError::UnknownSku("Z0001".to_string())),
+        basket.scan("Z0001")
+    );
+    assert!(basket.scan("B0001").is_err());
+    assert!(basket
+        .add_deal(deal("B0001", DealKind::Buy1Get1Free))
+        .is_err());
+    assert!(Deal::builder("A0001")
+        .kind(DealKind::BuyNGetMFree { buy: 0, free: 1 })
+        .build()
+        .is_err());
+    assert!(basket.events().is_empty());
+}
+
+#[test]
+fn test_event_log_round_trip() {
+    let catalog = catalog();
+    let mut basket = Basket::new(Arc::clone(&catalog));
+
+    basket.scan_quantity("A0002", 2).unwrap();
+    basket
+        .add_deal(deal("A0002", DealKind::Buy1Get1Free))
+        .unwrap();
+    basket.scan("A0001").unwrap();
+    basket.undo().unwrap();
+
+    let log = serde_json::to_string(basket.events()).unwrap();
+    let events: Vec<Event> = serde_json::from_str(&log).unwrap();
+    let replayed = Basket::replay(catalog, &events).unwrap();
+
+    assert_eq!(eur(399), replayed.total().unwrap());
+    assert_eq!(basket.receipt(), replayed.receipt());
+}
+
+#[test]
+fn test_baskets_are_shared_between_threads() {
+    let sessions: Vec<Basket> = (1..=4)
+        .map(|quantity| {
+            let mut basket = Basket::new(catalog());
+            basket.scan_quantity("A0003", quantity).unwrap();
+            basket
+        })
+        .collect();
+
+    let totals: Vec<Money> = std::thread::scope(|scope| {
+        let handles: Vec<_> = sessions
+            .iter()
+            .map(|basket| scope.spawn(|| basket.total().unwrap()))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    assert_eq!(vec![eur(250), eur(500), eur(750), eur(1000)], totals);
+}
+
+#[test]
+fn test_total_in_another_currency() {
+    let rates = ExchangeRates::from_csv("from,to,rate\nEUR,GBP,0.85\n").unwrap();
+    let mut basket = Basket::new(catalog());
+
+    basket.scan("A0001").unwrap();
+
+    let total = basket.total_in(CurrencyCode::Gbp, &rates).unwrap();
+
+    assert_eq!(eur(1299), total.total);
+    assert_eq!(Money::new(1104, CurrencyCode::Gbp), total.converted);
+}
+
+#[test]
+fn test_vat_on_discounted_lines() {
+    let catalog = Catalog::from_csv(
+        "sku,name,price,currency,category,unit,tax\n\
+         A0001,Water,1299,EUR,,,reduced\n\
+         A0002,Soap,399,EUR,,,\n",
+    )
+    .unwrap();
+    let mut basket = Basket::new(Arc::new(catalog));
+
+    basket.set_tax_policy(TaxPolicy {
+        rates: TaxRates {
+            standard: Percentage::from_percent(20).unwrap(),
+            reduced: Percentage::from_percent(5).unwrap(),
+        },
+        mode: PricingMode::Exclusive,
+    });
+    basket.scan("A0001").unwrap();
+    basket.scan_quantity("A0002", 2).unwrap();
+    basket
+        .add_deal(deal("A0002", DealKind::Buy1Get1Free))
+        .unwrap();
+
+    let receipt = basket.receipt().unwrap();
+    let taxes: Vec<(Option<u32>, Money)> = receipt
+        .taxes
+        .iter()
+        .map(|summary| (summary.rate.map(u32::from), summary.tax))
+        .collect();
+
+    // Only the soap that is paid for is taxed.
+    assert_eq!(vec![(Some(2000), eur(80)), (Some(500), eur(65))], taxes);
+    assert_eq!(eur(1698 + 145), receipt.total);
+}
+
+#[test]
+fn test_jurisdictions_per_store() {
+    let catalog = Arc::new(
+        Catalog::from_csv(
+            "sku,name,price,currency,category,unit,tax\n\
+             A0001,Water,1299,EUR,,,reduced\n\
+             A0002,Soap,399,EUR,,,\n",
+        )
+        .unwrap(),
+    );
+    let taxes = |path: &str| {
+        let mut basket = Basket::new(Arc::clone(&catalog));
+
+        basket.set_jurisdiction(Arc::new(TaxJurisdiction::from_path(path).unwrap()));
+        basket.set_clock(FixedClock(
+            "2024-03-01".parse::<chrono::NaiveDate>().unwrap().into(),
+        ));
+        basket.scan("A0001").unwrap();
+        basket.scan("A0002").unwrap();
+
+        let receipt = basket.receipt().unwrap();
+        let taxes: Vec<(Option<u32>, Money)> = receipt
+            .taxes
+            .iter()
+            .map(|summary| (summary.rate.map(u32::from), summary.tax))
+            .collect();
+
+        (taxes, receipt.total)
+    };
+
+    // German VAT is included in the prices.
+    assert_eq!(
+        (vec![(Some(1900), eur(64)), (Some(700), eur(85))], eur(1698)),
+        taxes("jurisdictions/de.json")
+    );
+    // Californian sales tax is added on top, and groceries are not taxed.
+    assert_eq!(
+        (
+            vec![(Some(725), eur(29)), (Some(0), eur(0))],
+            eur(1698 + 29)
+        ),
+        taxes("jurisdictions/us-ca.json")
+    );
+}
+
+#[test]
+fn test_promotions_file() {
+    let catalog = Arc::new(Catalog::from_path("catalog.csv").unwrap());
+    let promotions = Promotion::from_path("promotions.json", &catalog).unwrap();
+    let mut basket = Basket::new(Arc::clone(&catalog));
+
+    basket.scan_quantity("A0002", 2).unwrap();
+    for deal in promotions
+        .iter()
+        .filter(|promotion| promotion.id == "DEAL1")
+        .flat_map(|promotion| &promotion.deals)
+    {
+        basket.add_deal(deal.clone()).unwrap();
+    }
+
+    assert_eq!(eur(399), basket.total().unwrap());
+}
+
+#[test]
+fn test_sales_tax_receipt() {
+    let mut basket = Basket::new(catalog());
+
+    basket.set_jurisdiction(Arc::new(
+        TaxJurisdiction::from_path("jurisdictions/us-ca.json").unwrap(),
+    ));
+    basket.set_clock(FixedClock(
+        "2024-03-01".parse::<chrono::NaiveDate>().unwrap().into(),
+    ));
+    basket.scan("A0002").unwrap();
+
+    let rendered = basket
+        .receipt()
+        .unwrap()
+        .display_with(&CurrencyFormat::for_locale("en-US").unwrap())
+        .to_string();
+
+    assert!(rendered.contains("Sales tax 7.25% on €3.99"));
+    assert!(!rendered.contains("VAT"));
+}